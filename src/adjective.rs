@@ -0,0 +1,235 @@
+use crate::pos::AdjectivePattern;
+use crate::spelling;
+
+// Forms of adjectives: the headwords for each gender, and the comparative
+// and superlative with their English.
+
+// Adjectives that compare irregularly, with their English
+struct IrregularComparison {
+    latin: &'static str,
+    comparative: &'static str,
+    superlative: &'static str,
+    english_comparative: &'static [&'static str],
+    english_superlative: &'static [&'static str],
+}
+
+const IRREGULAR: [IrregularComparison; 5] = [
+    IrregularComparison {
+        latin: "bonus",
+        comparative: "melior",
+        superlative: "optimus",
+        english_comparative: &["better"],
+        english_superlative: &["best", "very good"],
+    },
+    IrregularComparison {
+        latin: "malus",
+        comparative: "peior",
+        superlative: "pessimus",
+        english_comparative: &["worse"],
+        english_superlative: &["worst", "very bad"],
+    },
+    IrregularComparison {
+        latin: "magnus",
+        comparative: "maior",
+        superlative: "maximus",
+        english_comparative: &["bigger", "greater", "larger"],
+        english_superlative: &["biggest", "greatest", "largest", "very big", "very great"],
+    },
+    IrregularComparison {
+        latin: "parvus",
+        comparative: "minor",
+        superlative: "minimus",
+        english_comparative: &["smaller", "less"],
+        english_superlative: &["smallest", "least", "very small"],
+    },
+    IrregularComparison {
+        latin: "multus",
+        comparative: "plus",
+        superlative: "plurimus",
+        english_comparative: &["more"],
+        english_superlative: &["most", "very many"],
+    },
+];
+
+// These six have superlatives in -limus, e.g. facillimus
+const LIMUS: [&str; 6] = [
+    "facilis",
+    "difficilis",
+    "similis",
+    "dissimilis",
+    "gracilis",
+    "humilis",
+];
+
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub comparative: String,
+    pub superlative: String,
+    pub english_comparative: Vec<String>,
+    pub english_superlative: Vec<String>,
+}
+
+// Work the pattern out from the headwords if the tag didn't give it:
+// bonus, bona, bonum and acer, acris, acre have three terminations,
+// fortis, forte two, and ingens, ingentis one.
+pub fn pattern_for(latin_parts: &[&str], pattern: Option<AdjectivePattern>) -> AdjectivePattern {
+    let guess_third = || match latin_parts {
+        [_, _, _] => 3,
+        [first, second] if first.ends_with("is") && second.ends_with('e') => 2,
+        _ => 1,
+    };
+    match pattern {
+        Some(AdjectivePattern::Third { terminations: None }) => AdjectivePattern::Third {
+            terminations: Some(guess_third()),
+        },
+        Some(pattern) => pattern,
+        None => match latin_parts {
+            [_, feminine, _] if feminine.ends_with('a') => AdjectivePattern::FirstSecond,
+            _ => AdjectivePattern::Third {
+                terminations: Some(guess_third()),
+            },
+        },
+    }
+}
+
+// The forms that each get a meaning question. For one-termination adjectives
+// the second form is the genitive, which only shows the stem.
+pub fn headwords(latin_parts: &[&str], pattern: AdjectivePattern) -> Vec<String> {
+    let parts = latin_parts.iter().map(|p| p.to_string());
+    match pattern {
+        AdjectivePattern::Third {
+            terminations: Some(1),
+        } => parts.take(1).collect(),
+        _ => parts.collect(),
+    }
+}
+
+// The comparative and superlative, built from the stem unless the adjective
+// is one of the irregular ones.
+pub fn comparison(
+    latin_parts: &[&str],
+    pattern: AdjectivePattern,
+    meanings: &[&str],
+) -> Option<Comparison> {
+    let masculine = latin_parts.first()?.to_lowercase();
+    // the tables are written without macrons
+    let plain = spelling::strip_macrons(&masculine);
+    if let Some(irregular) = IRREGULAR.iter().find(|i| i.latin == plain) {
+        return Some(Comparison {
+            comparative: irregular.comparative.to_string(),
+            superlative: irregular.superlative.to_string(),
+            english_comparative: to_strings(irregular.english_comparative),
+            english_superlative: to_strings(irregular.english_superlative),
+        });
+    }
+    // the stem is what is left of the feminine or the genitive,
+    // e.g. pulchr-a, ingent-is, fort-is
+    let stem = match (pattern, latin_parts) {
+        (AdjectivePattern::FirstSecond, [_, feminine, ..]) => feminine.strip_suffix('a')?,
+        (AdjectivePattern::FirstSecond, [masculine]) => masculine.strip_suffix("us")?,
+        (_, [_, genitive_or_feminine, ..]) if genitive_or_feminine.ends_with("is") => {
+            genitive_or_feminine.strip_suffix("is")?
+        }
+        (_, [masculine, ..]) => masculine.strip_suffix("is")?,
+        _ => return None,
+    }
+    .to_string();
+    let superlative = if masculine.ends_with("er") {
+        format!("{}rimus", masculine)
+    } else if LIMUS.contains(&plain.as_str()) {
+        format!("{}limus", stem)
+    } else {
+        format!("{}issimus", stem)
+    };
+    Some(Comparison {
+        comparative: format!("{}ior", stem),
+        superlative,
+        english_comparative: meanings
+            .iter()
+            .flat_map(|m| english_comparative(m))
+            .collect(),
+        english_superlative: meanings
+            .iter()
+            .flat_map(|m| english_superlative(m))
+            .collect(),
+    })
+}
+
+fn to_strings(forms: &[&str]) -> Vec<String> {
+    forms.iter().map(|f| f.to_string()).collect()
+}
+
+// "more wise", "rather wise", and for short words "wiser"
+fn english_comparative(meaning: &str) -> Vec<String> {
+    let mut forms = vec![format!("more {}", meaning), format!("rather {}", meaning)];
+    if let Some(stem) = inflecting_stem(meaning) {
+        forms.insert(0, with_ending(&stem, "er"));
+    }
+    forms
+}
+
+// "most wise", "very wise", and for short words "wisest"
+fn english_superlative(meaning: &str) -> Vec<String> {
+    let mut forms = vec![format!("most {}", meaning), format!("very {}", meaning)];
+    if let Some(stem) = inflecting_stem(meaning) {
+        forms.insert(0, with_ending(&stem, "est"));
+    }
+    forms
+}
+
+// A silent e takes the place of the ending's own e: wise -> wiser, wisest
+fn with_ending(stem: &str, ending: &str) -> String {
+    match stem.ends_with('e') {
+        true => format!("{}{}", stem, &ending[1..]),
+        false => format!("{}{}", stem, ending),
+    }
+}
+
+// Short English adjectives take -er and -est: happy -> happi-, big -> bigg-.
+// Anything longer, or a phrase, only gets "more" and "most".
+fn inflecting_stem(meaning: &str) -> Option<String> {
+    if meaning.is_empty() || meaning.contains(' ') {
+        return None;
+    }
+    let chars = meaning.chars().collect::<Vec<char>>();
+    let is_vowel = |c: char| "aeiou".contains(c);
+    // count groups of vowels, leaving out a silent e at the end
+    let sounded = meaning.strip_suffix('e').unwrap_or(meaning);
+    let mut syllables = 0;
+    let mut last_was_vowel = false;
+    for (num, c) in sounded.chars().enumerate() {
+        let vowel = is_vowel(c) || (c == 'y' && num > 0);
+        if vowel && !last_was_vowel {
+            syllables += 1;
+        }
+        last_was_vowel = vowel;
+    }
+    match chars.as_slice() {
+        [.., c, 'y'] if !is_vowel(*c) && syllables <= 2 => {
+            Some(format!("{}i", &meaning[..meaning.len() - 1]))
+        }
+        _ if syllables != 1 => None,
+        // double the last letter after a single short vowel, e.g. big, sad
+        [.., a, b, c] if !is_vowel(*a) && is_vowel(*b) && !is_vowel(*c) && !"wxy".contains(*c) => {
+            Some(format!("{}{}", meaning, c))
+        }
+        _ => Some(meaning.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_endings() {
+        assert_eq!(english_comparative("wise")[0], "wiser");
+        assert_eq!(english_superlative("wise")[0], "wisest");
+        assert_eq!(english_comparative("free")[0], "freer");
+        assert_eq!(english_comparative("sheer")[0], "sheerer");
+        assert_eq!(english_superlative("sheer")[0], "sheerest");
+        assert_eq!(english_comparative("big")[0], "bigger");
+        assert_eq!(english_superlative("happy")[0], "happiest");
+        assert_eq!(english_comparative("beautiful")[0], "more beautiful");
+    }
+}
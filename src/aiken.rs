@@ -0,0 +1,136 @@
+use crate::multichoice::{self, Choices, LeftOut};
+use crate::{Category, Question};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Moodle's Aiken format, see https://docs.moodle.org/en/Aiken_format
+// Each question is one line, then its options, then the letter of the
+// right one:
+//
+// portavi
+// A) I carried
+// B) I carry
+// C) I taught
+// ANSWER: A
+//
+// Aiken has one right answer per question and no marks, feedback or
+// categories, so part-mark answers count as wrong and questions with more
+// than one right answer are left out. Questions with more than one gap are
+// split up as for Moodle's multiple choice, see multichoice::one_gap_each.
+
+const LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+// Returns the questions that were left out
+pub fn write_categories(
+    path: &Path,
+    categories: &[Category],
+    choices: &Choices,
+) -> Result<LeftOut, Box<dyn Error>> {
+    let f = File::create(path)?;
+    let mut writer = BufWriter::new(f);
+    let mut left_out = LeftOut::default();
+    for category in categories {
+        let (questions, typed_gaps) = multichoice::one_gap_each(&category.questions);
+        left_out.typed_gaps += typed_gaps;
+        let questions = questions.iter().collect::<Vec<&Question>>();
+        let menus = multichoice::menus(&questions, choices);
+        for (question, menu) in questions.iter().zip(menus) {
+            let menu = match menu {
+                Some(menu) if menu.len() <= LETTERS.len() => menu,
+                _ => {
+                    left_out.no_menu += 1;
+                    continue;
+                }
+            };
+            let right = menu
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| a.mark == 100)
+                .map(|(num, _)| num)
+                .collect::<Vec<usize>>();
+            if right.len() != 1 {
+                left_out.no_menu += 1;
+                continue;
+            }
+            writeln!(writer, "{}", one_line(&question.latin))?;
+            for (letter, answer) in LETTERS.chars().zip(&menu) {
+                writeln!(writer, "{}) {}", letter, one_line(&answer.answer))?;
+            }
+            writeln!(writer, "ANSWER: {}\n", &LETTERS[right[0]..right[0] + 1])?;
+        }
+    }
+    writer.flush()?;
+    Ok(left_out)
+}
+
+// Everything in an Aiken question has to fit on one line
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{question, written};
+    use crate::{AnswerOption, GapType};
+
+    #[test]
+    fn answer_letter_follows_the_shuffle() {
+        let words = [
+            ("bonus", "good"),
+            ("malus", "bad"),
+            ("magnus", "great"),
+            ("parvus", "small"),
+            ("longus", "long\nand tall"),
+        ];
+        let questions = words
+            .iter()
+            .map(|(latin, english)| question(latin, english))
+            .collect::<Vec<Question>>();
+        for seed in 1..20 {
+            let choices = Choices { count: 4, seed };
+            let (left_out, text) = written(&format!("aiken_{}.txt", seed), |path| {
+                write_categories(path, &[Category::single("top", "A", &questions)], &choices)
+                    .unwrap()
+            });
+            assert_eq!(left_out, LeftOut::default());
+            let blocks = text.trim().split("\n\n").collect::<Vec<&str>>();
+            assert_eq!(blocks.len(), words.len());
+            for (block, (latin, english)) in blocks.iter().zip(&words) {
+                let lines = block.lines().collect::<Vec<&str>>();
+                assert_eq!(lines[0], *latin);
+                assert_eq!(lines.len(), 2 + choices.count);
+                let letter = lines.last().unwrap().strip_prefix("ANSWER: ").unwrap();
+                let right = format!("{}) {}", letter, one_line(english));
+                assert!(lines.contains(&right.as_str()), "{} in {:?}", right, lines);
+            }
+        }
+    }
+
+    #[test]
+    fn several_right_answers_are_left_out() {
+        let mut questions = vec![
+            question("bonus", "good"),
+            question("malus", "bad"),
+            question("civis", "citizen"),
+        ];
+        questions[2].gap_type = GapType::MultiChoice;
+        questions[2].answers.push(AnswerOption {
+            mark: 100,
+            answer: "fellow-citizen".to_string(),
+            feedback: String::new(),
+        });
+        let (left_out, text) = written("aiken_right.txt", |path| {
+            write_categories(
+                path,
+                &[Category::single("top", "A", &questions)],
+                &Choices::default(),
+            )
+            .unwrap()
+        });
+        assert_eq!(left_out.no_menu, 1);
+        assert!(!text.contains("civis"));
+    }
+}
@@ -0,0 +1,1370 @@
+use crate::adjective;
+use crate::config;
+use crate::irregular::{self, PartKind};
+use crate::multichoice::{self, Choices};
+use crate::pos::{case_markers, AdjectivePattern, Case, Gender, PartOfSpeech, Voice};
+use crate::reverse::{self, Direction, Marking};
+use crate::spelling;
+use crate::verb_cache::{self, VerbForms};
+use crate::{AnswerOption, Gap, GapType, Question, Record};
+// use english_past::{lookup, Verb};
+use csv::{ByteRecord, ReaderBuilder, StringRecord, Trim};
+use regex::Regex;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+// Which values of the Test column to import, e.g. "20", "18-20" or "18,19,22".
+// An empty filter lets every row through.
+#[derive(Debug, Clone)]
+pub struct TestFilter {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl TestFilter {
+    pub fn all() -> TestFilter {
+        TestFilter { ranges: Vec::new() }
+    }
+
+    pub fn parse(s: &str) -> Result<TestFilter, String> {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for part in s.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            let bounds = part.split('-').map(|b| b.trim()).collect::<Vec<&str>>();
+            let parse_num = |b: &str| {
+                b.parse::<u32>()
+                    .map_err(|_| format!("'{}' is not a test number", b))
+            };
+            match bounds.as_slice() {
+                [one] => {
+                    let num = parse_num(one)?;
+                    ranges.push((num, num));
+                }
+                [from, to] => {
+                    let (from, to) = (parse_num(from)?, parse_num(to)?);
+                    if from > to {
+                        return Err(format!("'{}' is not a valid range", part));
+                    }
+                    ranges.push((from, to));
+                }
+                _ => return Err(format!("'{}' is not a valid range", part)),
+            }
+        }
+        Ok(TestFilter { ranges })
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // Rows without a test number only get through an empty filter
+    pub fn matches(&self, test: Option<u32>) -> bool {
+        if self.is_all() {
+            return true;
+        }
+        match test {
+            Some(num) => self
+                .ranges
+                .iter()
+                .any(|(from, to)| *from <= num && num <= *to),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for TestFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_all() {
+            return write!(f, "all tests");
+        }
+        let parts = self
+            .ranges
+            .iter()
+            .map(|(from, to)| match from == to {
+                true => format!("{}", from),
+                false => format!("{}-{}", from, to),
+            })
+            .collect::<Vec<String>>();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+// How verbs are turned into questions
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VerbStrategy {
+    // a question for each principal part, asking what it means
+    #[default]
+    Separate,
+    // one question giving the first part, with a gap for each of the others
+    // and one for the meaning
+    PrincipalParts,
+}
+
+// Answers for a verb's other principal parts, e.g. "I carry" on the
+// question for portavi. In the feedback {given} is replaced by the part that
+// was answered and {wanted} by the part being asked for.
+#[derive(Debug, Clone)]
+pub struct WrongForms {
+    pub mark: u8,
+    pub feedback: String,
+}
+
+impl Default for WrongForms {
+    fn default() -> Self {
+        let config = config::get();
+        WrongForms {
+            mark: config.wrong_form_mark,
+            feedback: config.wrong_form_feedback,
+        }
+    }
+}
+
+impl WrongForms {
+    fn feedback_for(&self, given: PartKind, wanted: PartKind) -> String {
+        self.feedback
+            .replace("{given}", given.name())
+            .replace("{wanted}", wanted.name())
+    }
+}
+
+// Choices made when importing, which decide the questions built for each word
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub tests: TestFilter,
+    // separate meaning, genitive and gender questions for nouns
+    pub noun_parts: bool,
+    // comparative and superlative questions for adjectives
+    pub comparison: bool,
+    // Latin -> English, English -> Latin or both
+    pub direction: Direction,
+    // how Latin answers are marked in English -> Latin questions
+    pub marking: Marking,
+    // show macrons in the Latin of Latin -> English questions
+    pub macrons: bool,
+    // multiple choice menus instead of typed English answers
+    pub choices: Option<Choices>,
+    pub verbs: VerbStrategy,
+    // how answers giving the wrong principal part of a verb are marked
+    pub wrong_forms: WrongForms,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            tests: TestFilter::all(),
+            noun_parts: false,
+            comparison: false,
+            direction: Direction::default(),
+            marking: Marking::default(),
+            macrons: true,
+            choices: None,
+            verbs: VerbStrategy::default(),
+            wrong_forms: WrongForms::default(),
+        }
+    }
+}
+
+// Verb lookups can be slow, so verbs are built on a few worker threads
+const MAX_WORKERS: usize = 4;
+
+// The columns a vocab file must have. Test is optional.
+const REQUIRED_COLUMNS: [&str; 3] = ["Latin", "Part of Speech", "English"];
+
+// Something wrong with a vocab file, and where it is
+#[derive(Debug)]
+pub struct ImportError {
+    pub file: PathBuf,
+    pub line: Option<u64>,
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+impl ImportError {
+    pub(crate) fn new(
+        file: &Path,
+        line: Option<u64>,
+        field: Option<String>,
+        reason: &str,
+    ) -> ImportError {
+        ImportError {
+            file: file.to_path_buf(),
+            line,
+            field,
+            reason: reason.to_string(),
+        }
+    }
+
+    // Where the problem is, e.g. "ch20-vocab, line 4, English"
+    pub fn location(&self) -> String {
+        let mut location = self.file.display().to_string();
+        if let Some(line) = self.line {
+            location = format!("{}, line {}", location, line);
+        }
+        if let Some(field) = &self.field {
+            location = format!("{}, {}", location, field);
+        }
+        location
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location(), self.reason)
+    }
+}
+
+impl Error for ImportError {}
+
+// The questions from the rows that could be read, and the rows that couldn't
+#[derive(Debug, Default)]
+pub struct Import {
+    pub questions: Vec<Question>,
+    pub skipped: Vec<ImportError>,
+}
+
+// Turn a csv error into an ImportError naming the row and, if possible, the column
+fn row_error(file: &Path, line: u64, headers: &StringRecord, e: &csv::Error) -> ImportError {
+    let column = |index: Option<u64>| {
+        index
+            .and_then(|i| headers.get(i as usize))
+            .map(|name| name.to_string())
+    };
+    let (field, reason) = match e.kind() {
+        csv::ErrorKind::Utf8 { err, .. } => (
+            column(Some(err.field() as u64)),
+            "not valid UTF-8, save the file as CSV UTF-8".to_string(),
+        ),
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => (
+            None,
+            format!("has {} fields but the header has {}", len, expected_len),
+        ),
+        csv::ErrorKind::Deserialize { err, .. } => {
+            let reason = match err.kind() {
+                csv::DeserializeErrorKind::InvalidUtf8(_) => {
+                    "not valid UTF-8, save the file as CSV UTF-8".to_string()
+                }
+                csv::DeserializeErrorKind::ParseInt(_) => "expected a whole number".to_string(),
+                kind => kind.to_string(),
+            };
+            (column(err.field()), reason)
+        }
+        _ => (None, e.to_string()),
+    };
+    ImportError::new(file, Some(line), field, &reason)
+}
+
+// Read a vocab file and build questions for every word in it that passes the test filter.
+// Questions come back in the same order as the words in the file. Rows that
+// can't be read are skipped and listed in the Import rather than stopping it.
+// `progress` is called once for each question built.
+pub fn import_file(
+    file: &Path,
+    options: &ImportOptions,
+    progress: &dyn Fn(),
+) -> Result<Import, ImportError> {
+    let import = read_vocab(file, options, progress);
+    // written whether or not the import worked, as the lookups made so far
+    // are still good. Failing to write it only costs time on the next import.
+    let _ = verb_cache::save();
+    import
+}
+
+fn read_vocab(
+    file: &Path,
+    options: &ImportOptions,
+    progress: &dyn Fn(),
+) -> Result<Import, ImportError> {
+    // " Latin " is as good a header as "Latin"
+    let mut rdr = ReaderBuilder::new()
+        .trim(Trim::Headers)
+        .from_path(file)
+        .map_err(|e| ImportError::new(file, None, None, &e.to_string()))?;
+    let headers = match rdr.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return Err(row_error(file, 1, &StringRecord::new(), &e)),
+    };
+    for column in REQUIRED_COLUMNS.iter() {
+        if !headers.iter().any(|h| h == *column) {
+            return Err(ImportError::new(
+                file,
+                Some(1),
+                Some(column.to_string()),
+                "missing column in the header row",
+            ));
+        }
+    }
+    let byte_headers = headers.as_byte_record().clone();
+    let mut import = Import::default();
+    let mut records: Vec<(Record, PartOfSpeech)> = Vec::new();
+    let mut lines: Vec<u64> = Vec::new();
+    let mut raw = ByteRecord::new();
+    loop {
+        let line = rdr.position().line();
+        match rdr.read_byte_record(&mut raw) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => {
+                if let csv::ErrorKind::Io(_) = e.kind() {
+                    return Err(ImportError::new(file, Some(line), None, &e.to_string()));
+                }
+                import.skipped.push(row_error(file, line, &headers, &e));
+                continue;
+            }
+        }
+        let line = raw.position().map(|p| p.line()).unwrap_or(line);
+        let mut record: Record = match raw.deserialize(Some(&byte_headers)) {
+            Ok(record) => record,
+            Err(e) => {
+                import.skipped.push(row_error(file, line, &headers, &e));
+                continue;
+            }
+        };
+        record.latin = spelling::normalize(&record.latin);
+        record.english = spelling::normalize(&record.english);
+        if record.latin.trim().is_empty() {
+            import.skipped.push(ImportError::new(
+                file,
+                Some(line),
+                Some("Latin".to_string()),
+                "no Latin word",
+            ));
+            continue;
+        }
+        if record.english.trim().is_empty() {
+            import.skipped.push(ImportError::new(
+                file,
+                Some(line),
+                Some("English".to_string()),
+                "no English meaning",
+            ));
+            continue;
+        }
+        let part_of_speech: PartOfSpeech = match record.part_of_speech.parse() {
+            Ok(pos) => pos,
+            Err(e) => {
+                import.skipped.push(ImportError::new(
+                    file,
+                    Some(line),
+                    Some("Part of Speech".to_string()),
+                    &e,
+                ));
+                continue;
+            }
+        };
+        if options.tests.matches(record.test) {
+            records.push((record, part_of_speech));
+            lines.push(line);
+        }
+    }
+    // one slot per record, filled in as each one is built
+    let mut built: Vec<Option<Vec<Question>>> = Vec::new();
+    built.resize_with(records.len(), || None);
+
+    let (job_tx, job_rx) = mpsc::channel::<(usize, Record, PartOfSpeech)>();
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    let (tx, rx) = mpsc::channel();
+    let mut verb_count = 0;
+    let kinds = records
+        .iter()
+        .map(|(_, part_of_speech)| part_of_speech.clone())
+        .collect::<Vec<PartOfSpeech>>();
+    for (num, (record, part_of_speech)) in records.into_iter().enumerate() {
+        match &part_of_speech {
+            pos if pos.is_verb() => {
+                job_tx.send((num, record, part_of_speech)).unwrap();
+                verb_count += 1;
+            }
+            _ => {
+                let word_questions = build_word(record, &part_of_speech, options);
+                for _ in &word_questions {
+                    progress();
+                }
+                built[num] = Some(word_questions);
+            }
+        }
+    }
+    drop(job_tx);
+    let mut workers = Vec::new();
+    for _ in 0..MAX_WORKERS.min(verb_count) {
+        let job_rx = Arc::clone(&job_rx);
+        let tx1 = mpsc::Sender::clone(&tx);
+        let options = options.clone();
+        workers.push(thread::spawn(move || loop {
+            // the lock is released as soon as a job has been taken
+            let job = job_rx.lock().unwrap().recv();
+            let (num, record, part_of_speech) = match job {
+                Ok(job) => job,
+                Err(_) => break,
+            };
+            let verb_questions = build_word(record, &part_of_speech, &options);
+            tx1.send((num, verb_questions)).unwrap();
+        }));
+    }
+    drop(tx);
+    for (num, verb_questions) in &rx {
+        for _ in &verb_questions {
+            progress();
+        }
+        built[num] = Some(verb_questions);
+    }
+    for worker in workers {
+        // a worker that died leaves its verb's slot empty, which is reported below
+        let _ = worker.join();
+    }
+    // each word's part of speech and how many questions it has
+    let mut words: Vec<(PartOfSpeech, usize)> = Vec::new();
+    for ((slot, line), part_of_speech) in built.into_iter().zip(lines).zip(kinds) {
+        match slot {
+            Some(mut word_questions) => {
+                words.push((part_of_speech, word_questions.len()));
+                import.questions.append(&mut word_questions);
+            }
+            None => import.skipped.push(ImportError::new(
+                file,
+                Some(line),
+                Some("English".to_string()),
+                "questions for this verb could not be built",
+            )),
+        }
+    }
+    import.skipped.sort_by_key(|e| e.line);
+    // reversed after everything is built, so words that share an English
+    // meaning (et, atque: and) end up in one question
+    import.questions = apply_direction(import.questions, options);
+    // the Latin -> English questions are still first, in the order of `words`
+    if let Some(choices) = &options.choices {
+        if options.direction != Direction::EnglishToLatin {
+            multichoice::apply(&mut import.questions, &words, choices);
+        }
+    }
+    Ok(import)
+}
+
+// Turn built Latin -> English questions the way the options say, then take
+// the macrons off the Latin prompts if asked. They only come off after the
+// English -> Latin answers have been made from them.
+pub fn apply_direction(questions: Vec<Question>, options: &ImportOptions) -> Vec<Question> {
+    let mut questions = reverse::apply(questions, options.direction, &options.marking);
+    if !options.macrons {
+        for question in questions.iter_mut().filter(|q| !q.reversed) {
+            question.latin = spelling::strip_macrons(&question.latin);
+        }
+    }
+    questions
+}
+
+// Build the questions for one word from a vocab list
+pub fn build_word(
+    record: Record,
+    part_of_speech: &PartOfSpeech,
+    options: &ImportOptions,
+) -> Vec<Question> {
+    let mut questions: Vec<Question> = Vec::new();
+    match part_of_speech {
+        PartOfSpeech::Verb { .. } if options.verbs == VerbStrategy::PrincipalParts => {
+            questions.push(build_principal_parts(
+                record.latin,
+                record.english,
+                &options.marking,
+            ));
+        }
+        PartOfSpeech::Verb { .. } => {
+            questions = build_verb(
+                record.latin,
+                record.english,
+                part_of_speech,
+                &options.wrong_forms,
+            );
+        }
+        PartOfSpeech::Noun { gender, .. } if options.noun_parts => {
+            questions = build_noun(record.latin, record.english, *gender);
+        }
+        PartOfSpeech::Preposition => {
+            questions.push(build_preposition(record.latin, record.english));
+        }
+        PartOfSpeech::Adjective { pattern } => {
+            questions = build_adjective(record.latin, record.english, *pattern, options.comparison);
+        }
+        _ => build_non_verb(&mut questions, record.latin, record.english),
+    }
+    for question in questions.iter_mut() {
+        question.test = record.test;
+    }
+    questions
+}
+
+pub fn clean_text(s: &str) -> String {
+    let re1 = Regex::new(r"\(.*\)").unwrap();
+    // every marker pos::case_markers understands
+    let re2 = Regex::new(r"\s*\b(NOM|VOC|ACC|GEN|DAT|ABL)\b").unwrap();
+    let re5 = Regex::new(r";").unwrap();
+    let re6 = Regex::new(r"/").unwrap();
+    let s = re1.replace_all(s, "");
+    let s = re2.replace_all(&s, "");
+    let s = re5.replace_all(&s, ",");
+    let s = re6.replace_all(&s, ",");
+    format!("{}", s)
+}
+
+pub fn build_non_verb(questions: &mut Vec<Question>, latin: String, english: String) {
+    let mut answer_options: Vec<AnswerOption> = Vec::new();
+    let english = clean_text(&english);
+    for answer in english.split(",").collect::<Vec<&str>>() {
+        let answer_option = AnswerOption {
+            mark: 100,
+            answer: answer.trim().to_string(),
+            feedback: config::correct_feedback(),
+        };
+        answer_options.push(answer_option);
+    }
+    let question = Question::new(latin, answer_options);
+    questions.push(question);
+}
+
+// Nouns split like verbs: the nominative and genitive get a question each,
+// and the gender is picked from a list, e.g. for "desperatio, desperationis"
+// desperatio = despondency, desperationis = of the despondency,
+// desperatio is feminine.
+pub fn build_noun(latin: String, english: String, gender: Option<Gender>) -> Vec<Question> {
+    let mut questions: Vec<Question> = Vec::new();
+    let english = clean_text(&english);
+    let meanings = english
+        .split(",")
+        .map(|m| m.trim())
+        .filter(|m| !m.is_empty())
+        .collect::<Vec<&str>>();
+    let latin_parts = latin.split(",").map(|p| p.trim()).collect::<Vec<&str>>();
+    let nominative = latin_parts[0];
+    // nominative
+    let mut answer_options: Vec<AnswerOption> = Vec::new();
+    for meaning in &meanings {
+        let answer_option = AnswerOption {
+            mark: 100,
+            answer: meaning.to_string(),
+            feedback: config::correct_feedback(),
+        };
+        answer_options.push(answer_option);
+    }
+    questions.push(Question::new(nominative.to_string(), answer_options));
+    // genitive
+    if latin_parts.len() > 1 {
+        let mut answer_options: Vec<AnswerOption> = Vec::new();
+        for meaning in &meanings {
+            let bare = meaning
+                .trim_start_matches("the ")
+                .trim_start_matches("a ")
+                .trim_start_matches("an ");
+            for answer in &[format!("of the {}", bare), format!("of {}", bare)] {
+                let answer_option = AnswerOption {
+                    mark: 100,
+                    answer: answer.to_string(),
+                    feedback: config::correct_feedback(),
+                };
+                answer_options.push(answer_option);
+            }
+            // the right word in the wrong case
+            let answer_option_close = AnswerOption {
+                mark: 50,
+                answer: meaning.to_string(),
+                feedback: "Close! What case is this?".to_string(),
+            };
+            answer_options.push(answer_option_close);
+        }
+        questions.push(Question::new(latin_parts[1].to_string(), answer_options));
+    }
+    // gender
+    if let Some(gender) = gender {
+        questions.push(Question {
+            gap_type: GapType::MultiChoice,
+            ..Question::new(
+                format!("{}: which gender?", nominative),
+                gender_options(gender),
+            )
+        });
+    }
+    questions
+}
+
+fn meaning_question(latin: &str, answers: &[String]) -> Question {
+    let mut answer_options: Vec<AnswerOption> = Vec::new();
+    for answer in answers {
+        let answer_option = AnswerOption {
+            mark: 100,
+            answer: answer.to_string(),
+            feedback: config::correct_feedback(),
+        };
+        answer_options.push(answer_option);
+    }
+    Question::new(latin.to_string(), answer_options)
+}
+
+// One question for each gender's headword (bonus, bona, bonum), or just the
+// nominative for one-termination adjectives like ingens, ingentis. With
+// `comparison`, the comparative and superlative get a question each too.
+pub fn build_adjective(
+    latin: String,
+    english: String,
+    pattern: Option<AdjectivePattern>,
+    comparison: bool,
+) -> Vec<Question> {
+    let mut questions: Vec<Question> = Vec::new();
+    let english = clean_text(&english);
+    let meanings = english
+        .split(",")
+        .map(|m| m.trim())
+        .filter(|m| !m.is_empty())
+        .collect::<Vec<&str>>();
+    let latin_parts = latin.split(",").map(|p| p.trim()).collect::<Vec<&str>>();
+    let pattern = adjective::pattern_for(&latin_parts, pattern);
+    let answers = meanings
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<String>>();
+    for headword in adjective::headwords(&latin_parts, pattern) {
+        questions.push(meaning_question(&headword, &answers));
+    }
+    if comparison {
+        if let Some(forms) = adjective::comparison(&latin_parts, pattern, &meanings) {
+            questions.push(meaning_question(
+                &forms.comparative,
+                &forms.english_comparative,
+            ));
+            questions.push(meaning_question(
+                &forms.superlative,
+                &forms.english_superlative,
+            ));
+        }
+    }
+    questions
+}
+
+// A preposition gets its meaning, and then a second gap for the case it
+// takes, read from the markers in the English, e.g. "pro: instead of ABL".
+// Prepositions taking two cases (in: in, on ABL; into, onto ACC) accept either.
+pub fn build_preposition(latin: String, english: String) -> Question {
+    let cases = case_markers(&english);
+    let takes = cases
+        .iter()
+        .map(|c| c.name())
+        .collect::<Vec<&str>>()
+        .join(" or the ");
+    let english = clean_text(&english);
+    let mut answer_options: Vec<AnswerOption> = Vec::new();
+    for answer in english
+        .split(",")
+        .map(|a| a.trim())
+        .filter(|a| !a.is_empty())
+    {
+        let feedback = match cases.is_empty() {
+            true => config::correct_feedback(),
+            false => format!(
+                "{} {} takes the {}.",
+                config::correct_feedback(),
+                latin.trim(),
+                takes
+            ),
+        };
+        let answer_option = AnswerOption {
+            mark: 100,
+            answer: answer.to_string(),
+            feedback,
+        };
+        answer_options.push(answer_option);
+    }
+    let mut extra_gaps: Vec<Gap> = Vec::new();
+    if !cases.is_empty() {
+        extra_gaps.push(Gap {
+            prompt: "+ which case?".to_string(),
+            gap_type: GapType::MultiChoice,
+            answers: case_options(&cases, &takes),
+        });
+    }
+    Question {
+        extra_gaps,
+        ..Question::new(latin.trim().to_string(), answer_options)
+    }
+}
+
+// Accusative and ablative are always offered, and any other case the
+// preposition takes, e.g. the genitive for causa
+fn case_options(cases: &[Case], takes: &str) -> Vec<AnswerOption> {
+    let mut offered = vec![Case::Accusative, Case::Ablative];
+    for case in cases {
+        if !offered.contains(case) {
+            offered.push(*case);
+        }
+    }
+    offered
+        .iter()
+        .map(|case| match cases.contains(case) {
+            true => AnswerOption {
+                mark: 100,
+                answer: case.name().to_string(),
+                feedback: config::correct_feedback(),
+            },
+            false => AnswerOption {
+                mark: 0,
+                answer: case.name().to_string(),
+                feedback: format!("No, it takes the {}.", takes),
+            },
+        })
+        .collect()
+}
+
+// The three genders in a fixed order, with the right one(s) marked correct
+pub fn gender_options(gender: Gender) -> Vec<AnswerOption> {
+    let correct = match gender {
+        Gender::Masculine => vec![Gender::Masculine],
+        Gender::Feminine => vec![Gender::Feminine],
+        Gender::Neuter => vec![Gender::Neuter],
+        Gender::Common => vec![Gender::Masculine, Gender::Feminine],
+    };
+    [
+        (Gender::Masculine, "masculine"),
+        (Gender::Feminine, "feminine"),
+        (Gender::Neuter, "neuter"),
+    ]
+    .iter()
+    .map(|(g, name)| match correct.contains(g) {
+        true => AnswerOption {
+            mark: 100,
+            answer: name.to_string(),
+            feedback: config::correct_feedback(),
+        },
+        false => AnswerOption {
+            mark: 0,
+            answer: name.to_string(),
+            feedback: format!("No, this noun is {}.", gender.name()),
+        },
+    })
+    .collect()
+}
+
+// Use the part of speech tag if it says so, otherwise look at the principal parts
+pub fn verb_voice(latin_parts: &[&str], part_of_speech: &PartOfSpeech) -> Voice {
+    if let PartOfSpeech::Verb {
+        voice: Some(voice), ..
+    } = part_of_speech
+    {
+        return *voice;
+    }
+    let first = latin_parts.first().map(|p| p.trim()).unwrap_or("");
+    let perfect_is_passive = latin_parts
+        .get(2)
+        .map(|p| p.trim().ends_with(" sum"))
+        .unwrap_or(false);
+    if first.ends_with("or") {
+        Voice::Deponent
+    } else if perfect_is_passive {
+        Voice::SemiDeponent
+    } else {
+        Voice::Active
+    }
+}
+
+// One question for a principal part, with an answer for each English meaning
+fn verb_question(
+    latin_part: &str,
+    verb_collection: &[VerbForms],
+    english_form: &dyn Fn(&VerbForms) -> String,
+) -> Question {
+    let config = config::get();
+    let mut answer_options: Vec<AnswerOption> = Vec::new();
+    for verb in verb_collection {
+        let answer_option = AnswerOption {
+            mark: 100,
+            answer: english_form(verb),
+            feedback: config::correct_feedback(),
+        };
+        answer_options.push(answer_option);
+        // prepare partially correct answer with wildcards
+        let answer_option_close = AnswerOption {
+            mark: config.close_mark,
+            answer: verb.asterisked.clone(),
+            feedback: config.close_feedback.clone(),
+        };
+        answer_options.push(answer_option_close);
+    }
+    Question::new(latin_part.trim().to_string(), answer_options)
+}
+
+// Students who translate the passive form literally get no marks, but are told why.
+// Moodle uses the first answer that matches, so these go before the wildcards.
+fn add_passive_traps(
+    question: &mut Question,
+    verb_collection: &[VerbForms],
+    passive_form: &dyn Fn(&VerbForms) -> String,
+) {
+    let position = question
+        .answers
+        .iter()
+        .position(|a| a.mark < 100)
+        .unwrap_or(question.answers.len());
+    for (offset, verb) in verb_collection.iter().enumerate() {
+        let trap = AnswerOption {
+            mark: 0,
+            answer: passive_form(verb),
+            feedback: "This verb is passive in form but active in meaning.".to_string(),
+        };
+        question.answers.insert(position + offset, trap);
+    }
+}
+
+// Each question is given the English of the verb's other parts as answers,
+// with feedback saying which part that was. They go in before the wildcards,
+// as Moodle uses the first answer that matches.
+fn add_wrong_forms(questions: &mut [Question], kinds: &[PartKind], wrong_forms: &WrongForms) {
+    let originals = questions.to_vec();
+    for (question, wanted) in questions.iter_mut().zip(kinds) {
+        let mut position = question
+            .answers
+            .iter()
+            .position(|a| a.mark < 100)
+            .unwrap_or(question.answers.len());
+        for (other, given) in originals.iter().zip(kinds) {
+            if given == wanted {
+                continue;
+            }
+            for answer in other.answers.iter().filter(|a| a.mark == 100) {
+                let taken = question
+                    .answers
+                    .iter()
+                    .any(|a| a.answer.to_lowercase() == answer.answer.to_lowercase());
+                if taken {
+                    continue;
+                }
+                let wrong_form = AnswerOption {
+                    mark: wrong_forms.mark,
+                    answer: answer.answer.clone(),
+                    feedback: wrong_forms.feedback_for(*given, *wanted),
+                };
+                question.answers.insert(position, wrong_form);
+                position += 1;
+            }
+        }
+    }
+}
+
+// Irregular verbs have their English built part by part, see irregular.rs
+fn build_irregular(
+    parts: Vec<irregular::PrincipalPart>,
+    wrong_forms: &WrongForms,
+) -> Vec<Question> {
+    let mut questions: Vec<Question> = Vec::new();
+    let kinds = parts.iter().map(|p| p.kind).collect::<Vec<PartKind>>();
+    for part in parts {
+        let mut answer_options: Vec<AnswerOption> = Vec::new();
+        for english in part.english {
+            let answer_option = AnswerOption {
+                mark: 100,
+                answer: english,
+                feedback: config::correct_feedback(),
+            };
+            answer_options.push(answer_option);
+        }
+        let question = Question::new(part.latin, answer_options);
+        questions.push(question);
+    }
+    add_wrong_forms(&mut questions, &kinds, wrong_forms);
+    questions
+}
+
+pub fn build_verb(
+    latin: String,
+    english: String,
+    part_of_speech: &PartOfSpeech,
+    wrong_forms: &WrongForms,
+) -> Vec<Question> {
+    let mut questions: Vec<Question> = Vec::new();
+    let english = english.replace("I ", "");
+    let english = clean_text(&english);
+    let answers = english.split(",").collect::<Vec<&str>>();
+    let latin_parts = latin.split(",").collect::<Vec<&str>>();
+    // sum, eo, fero and friends are dealt with before anything is looked up
+    if let Some(parts) = irregular::principal_parts(&latin_parts, &answers) {
+        return build_irregular(parts, wrong_forms);
+    }
+    let mut verb_collection: Vec<VerbForms> = Vec::new();
+    for answer in &answers {
+        let verb = verb_cache::lookup(answer.trim());
+        verb_collection.push(verb);
+    }
+    let voice = verb_voice(&latin_parts, part_of_speech);
+    //present tense
+    let mut present = verb_question(latin_parts[0], &verb_collection, &|verb| {
+        format!("I {}", verb.present)
+    });
+    if voice == Voice::Deponent {
+        add_passive_traps(&mut present, &verb_collection, &|verb| {
+            format!("I am {}", verb.past_part)
+        });
+    }
+    questions.push(present);
+    // infinitive
+    if latin_parts.len() > 1 {
+        let mut infinitive = verb_question(latin_parts[1], &verb_collection, &|verb| {
+            format!("to {}", verb.present)
+        });
+        if voice == Voice::Deponent {
+            add_passive_traps(&mut infinitive, &verb_collection, &|verb| {
+                format!("to be {}", verb.past_part)
+            });
+        }
+        questions.push(infinitive);
+    }
+    // perfect tense, e.g. portavi, or conatus sum for (semi-)deponents
+    if latin_parts.len() > 2 {
+        let mut perfect = verb_question(latin_parts[2], &verb_collection, &|verb| {
+            format!("I {}", verb.past_simple)
+        });
+        if voice != Voice::Active {
+            add_passive_traps(&mut perfect, &verb_collection, &|verb| {
+                format!("I was {}", verb.past_part)
+            });
+        }
+        questions.push(perfect);
+    }
+    // perfect participle: passive for most verbs, active for (semi-)deponents
+    if latin_parts.len() > 3 {
+        let participle = match voice {
+            Voice::Active => verb_question(latin_parts[3], &verb_collection, &|verb| {
+                format!("having been {}", verb.past_part)
+            }),
+            _ => verb_question(latin_parts[3], &verb_collection, &|verb| {
+                format!("having {}", verb.past_part)
+            }),
+        };
+        questions.push(participle);
+    }
+    let kinds = [
+        PartKind::Present,
+        PartKind::Infinitive,
+        PartKind::Perfect,
+        PartKind::PerfectParticiple,
+    ];
+    add_wrong_forms(&mut questions, &kinds, wrong_forms);
+    questions
+}
+
+// One question for the whole verb, e.g. for "porto, portare, portavi, portatus"
+// porto, ____, ____, ____ — meaning ____
+// Each gap is marked on its own, and the Latin gaps give part marks for a
+// form without its macrons or with the wrong ending, as in English -> Latin
+// questions.
+pub fn build_principal_parts(latin: String, english: String, marking: &Marking) -> Question {
+    let latin_parts = latin
+        .split(",")
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<&str>>();
+    let english = clean_text(&english.replace("I ", ""));
+    let mut meanings: Vec<AnswerOption> = Vec::new();
+    for meaning in english
+        .split(",")
+        .map(|m| m.trim())
+        .filter(|m| !m.is_empty())
+    {
+        for answer in &[format!("I {}", meaning), meaning.to_string()] {
+            meanings.push(AnswerOption {
+                mark: 100,
+                answer: answer.to_string(),
+                feedback: config::correct_feedback(),
+            });
+        }
+    }
+    let first_part = latin_parts.first().copied().unwrap_or("");
+    // a word with no other parts, e.g. inquit, is only asked its meaning
+    if latin_parts.len() < 2 {
+        return Question::new(format!("{} — meaning", first_part), meanings);
+    }
+    let mut gaps: Vec<Gap> = latin_parts
+        .iter()
+        .skip(1)
+        .map(|part| Gap {
+            prompt: ",".to_string(),
+            gap_type: GapType::ShortAnswer,
+            answers: reverse::latin_answers(part, marking),
+        })
+        .collect();
+    gaps.push(Gap {
+        prompt: "— meaning".to_string(),
+        gap_type: GapType::ShortAnswer,
+        answers: meanings,
+    });
+    // the first gap is the question's own, the rest follow it
+    let first = gaps.remove(0);
+    Question {
+        gap_type: first.gap_type,
+        extra_gaps: gaps,
+        ..Question::new(format!("{},", first_part), first.answers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{temp_path, written};
+    use crate::{moodle, Category};
+    use std::fs;
+
+    fn verb(present: &str, past_part: &str) -> VerbForms {
+        VerbForms {
+            present: present.to_string(),
+            past_simple: String::new(),
+            past_part: past_part.to_string(),
+            asterisked: String::new(),
+        }
+    }
+
+    fn untagged_verb() -> PartOfSpeech {
+        PartOfSpeech::Verb {
+            conjugation: None,
+            voice: None,
+        }
+    }
+
+    #[test]
+    fn voice_from_principal_parts() {
+        let verb = untagged_verb();
+        assert_eq!(
+            verb_voice(&["conor", " conari", " conatus sum"], &verb),
+            Voice::Deponent
+        );
+        assert_eq!(
+            verb_voice(&["audeo", " audere", " ausus sum"], &verb),
+            Voice::SemiDeponent
+        );
+        assert_eq!(
+            verb_voice(&["porto", " portare", " portavi", " portatus"], &verb),
+            Voice::Active
+        );
+    }
+
+    #[test]
+    fn voice_tag_wins() {
+        let tagged = PartOfSpeech::Verb {
+            conjugation: None,
+            voice: Some(Voice::Deponent),
+        };
+        assert_eq!(verb_voice(&["loquor"], &tagged), Voice::Deponent);
+        let active = PartOfSpeech::Verb {
+            conjugation: None,
+            voice: Some(Voice::Active),
+        };
+        assert_eq!(
+            verb_voice(&["conor", " conari", " conatus sum"], &active),
+            Voice::Active
+        );
+    }
+
+    #[test]
+    fn passive_traps_go_before_the_wildcards() {
+        let mut question = Question::new(
+            "conatus sum".to_string(),
+            vec![
+                AnswerOption {
+                    mark: 100,
+                    answer: "I tried".to_string(),
+                    feedback: String::new(),
+                },
+                AnswerOption {
+                    mark: 0,
+                    answer: "*".to_string(),
+                    feedback: String::new(),
+                },
+            ],
+        );
+        let verbs = [verb("try", "tried"), verb("attempt", "attempted")];
+        add_passive_traps(&mut question, &verbs, &|verb| {
+            format!("I was {}", verb.past_part)
+        });
+        let answers = question
+            .answers
+            .iter()
+            .map(|a| (a.answer.as_str(), a.mark))
+            .collect::<Vec<(&str, u8)>>();
+        assert_eq!(
+            answers,
+            [
+                ("I tried", 100),
+                ("I was tried", 0),
+                ("I was attempted", 0),
+                ("*", 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_forms_go_before_the_wildcards() {
+        verb_cache::add_override("carry", "carry", "carried", "carried");
+        let wrong_forms = WrongForms {
+            mark: 25,
+            feedback: config::Config::default().wrong_form_feedback,
+        };
+        let questions = build_verb(
+            "porto, portare, portavi, portatus".to_string(),
+            "I carry".to_string(),
+            &untagged_verb(),
+            &wrong_forms,
+        );
+        let portavi = &questions[2];
+        assert_eq!(portavi.latin, "portavi");
+        let answers = portavi
+            .answers
+            .iter()
+            .map(|a| (a.answer.as_str(), a.mark))
+            .collect::<Vec<(&str, u8)>>();
+        assert_eq!(
+            answers,
+            [
+                ("I carried", 100),
+                ("I carry", 25),
+                ("to carry", 25),
+                ("having been carried", 25),
+                ("*carry*", config::get().close_mark)
+            ]
+        );
+        assert_eq!(
+            portavi.answers[1].feedback,
+            "That is the present tense — this is the perfect tense."
+        );
+    }
+
+    #[test]
+    fn principal_parts_gaps() {
+        let question = build_principal_parts(
+            "porto, portare, portavi, portatus".to_string(),
+            "I carry".to_string(),
+            &Marking::default(),
+        );
+        assert_eq!(question.latin, "porto,");
+        let prompts = question
+            .extra_gaps
+            .iter()
+            .map(|g| g.prompt.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(prompts, [",", ",", "— meaning"]);
+        assert_eq!(question.answers[0].answer, "portare");
+    }
+
+    #[test]
+    fn one_part_keeps_its_meaning_prompt() {
+        let question = build_principal_parts(
+            "inquit".to_string(),
+            "he says".to_string(),
+            &Marking::default(),
+        );
+        assert_eq!(question.latin, "inquit — meaning");
+        assert!(question.extra_gaps.is_empty());
+        assert!(question.answers.iter().any(|a| a.answer == "he says"));
+    }
+
+    #[test]
+    fn nouns_split_into_three_questions() {
+        let questions = build_noun(
+            "desperatio, desperationis".to_string(),
+            "despair, the despondency".to_string(),
+            Some(Gender::Feminine),
+        );
+        let latin = questions
+            .iter()
+            .map(|q| q.latin.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            latin,
+            ["desperatio", "desperationis", "desperatio: which gender?"]
+        );
+        let genitive = questions[1]
+            .answers
+            .iter()
+            .map(|a| (a.answer.as_str(), a.mark))
+            .collect::<Vec<(&str, u8)>>();
+        assert_eq!(
+            genitive,
+            [
+                ("of the despair", 100),
+                ("of despair", 100),
+                ("despair", 50),
+                ("of the despondency", 100),
+                ("of despondency", 100),
+                ("the despondency", 50)
+            ]
+        );
+        assert_eq!(
+            questions[1].answers[2].feedback,
+            "Close! What case is this?"
+        );
+        let gender = &questions[2];
+        assert_eq!(gender.gap_type, GapType::MultiChoice);
+        assert!(moodle::moodle_cloze(gender).starts_with("{1:MULTICHOICE:"));
+        let right = gender
+            .answers
+            .iter()
+            .filter(|a| a.mark == 100)
+            .map(|a| a.answer.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(right, ["feminine"]);
+    }
+
+    #[test]
+    fn common_gender_takes_either() {
+        let options = gender_options(Gender::Common);
+        let marks = options
+            .iter()
+            .map(|a| (a.answer.as_str(), a.mark))
+            .collect::<Vec<(&str, u8)>>();
+        assert_eq!(
+            marks,
+            [("masculine", 100), ("feminine", 100), ("neuter", 0)]
+        );
+        assert_eq!(
+            options[2].feedback,
+            "No, this noun is masculine or feminine."
+        );
+    }
+
+    #[test]
+    fn case_markers_are_cleaned() {
+        assert_eq!(clean_text("instead of ABL"), "instead of");
+        assert_eq!(clean_text("O NOM/VOC friend"), "O, friend");
+        assert_eq!(clean_text("give DAT; hand over ACC"), "give, hand over");
+    }
+
+    // Read a vocab file written just for the test
+    fn read(name: &str, contents: &[u8]) -> Result<Import, ImportError> {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+        let import = read_vocab(&path, &ImportOptions::default(), &|| {});
+        fs::remove_file(&path).unwrap();
+        import
+    }
+
+    #[test]
+    fn bad_rows_are_skipped() {
+        let import = read(
+            "bad_rows.csv",
+            b"Latin,Part of Speech,English,Test\n\
+              et,conj,and,20\n\
+              bonus,adj 9,good,20\n\
+              hic,adv,here,twenty\n\
+              m\xe9us,adj,my,20\n\
+              ,conj,but,20\n\
+              sed,conj,but,20\n",
+        )
+        .unwrap();
+        let latin = import
+            .questions
+            .iter()
+            .map(|q| q.latin.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(latin, ["et", "sed"]);
+        let skipped = import
+            .skipped
+            .iter()
+            .map(|e| (e.line, e.field.as_deref(), e.reason.as_str()))
+            .collect::<Vec<(Option<u64>, Option<&str>, &str)>>();
+        assert_eq!(
+            skipped,
+            [
+                (
+                    Some(3),
+                    Some("Part of Speech"),
+                    "'9' not understood in 'adj 9'"
+                ),
+                (Some(4), Some("Test"), "expected a whole number"),
+                (
+                    Some(5),
+                    Some("Latin"),
+                    "not valid UTF-8, save the file as CSV UTF-8"
+                ),
+                (Some(6), Some("Latin"), "no Latin word"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_column_stops_the_import() {
+        let e = read("missing_column.csv", b"Latin,English\net,and\n").unwrap_err();
+        assert_eq!(e.line, Some(1));
+        assert_eq!(e.field.as_deref(), Some("Part of Speech"));
+        assert_eq!(e.reason, "missing column in the header row");
+    }
+
+    #[test]
+    fn questions_keep_the_order_of_the_file() {
+        verb_cache::add_override("carry", "carry", "carried", "carried");
+        verb_cache::add_override("teach", "teach", "taught", "taught");
+        let vocab = temp_path("mixed_kinds.csv");
+        fs::write(
+            &vocab,
+            "Latin,Part of Speech,English,Test\n\
+             \"porto, portare, portavi, portatus\",verb 1,I carry,20\n\
+             et,conj,and,20\n\
+             \"doceo, docere, docui, doctus\",verb 2,I teach,20\n\
+             pro,prep,instead of ABL,20\n\
+             \"bonus, bona, bonum\",adj 212,good,20\n",
+        )
+        .unwrap();
+        let xml = |name: &str| {
+            let import = import_file(&vocab, &ImportOptions::default(), &|| {}).unwrap();
+            let (_, xml) = written(name, |path| {
+                moodle::write_categories(
+                    path,
+                    &[Category::single("top/A_20", "A_20", &import.questions)],
+                )
+                .unwrap()
+            });
+            (import, xml)
+        };
+        let (import, first) = xml("mixed_kinds_1.xml");
+        let (_, second) = xml("mixed_kinds_2.xml");
+        fs::remove_file(&vocab).unwrap();
+        assert!(import.skipped.is_empty());
+        let latin = import
+            .questions
+            .iter()
+            .map(|q| q.latin.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            latin,
+            [
+                "porto", "portare", "portavi", "portatus", "et", "doceo", "docere", "docui",
+                "doctus", "pro", "bonus", "bona", "bonum"
+            ]
+        );
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_filter_ranges() {
+        let filter = TestFilter::parse("18-20, 22").unwrap();
+        assert!(filter.matches(Some(18)));
+        assert!(filter.matches(Some(20)));
+        assert!(filter.matches(Some(22)));
+        assert!(!filter.matches(Some(21)));
+        assert!(!filter.matches(None));
+        assert_eq!(filter.to_string(), "18-20,22");
+        assert_eq!(TestFilter::parse(" 20 - 20 ").unwrap().to_string(), "20");
+    }
+
+    #[test]
+    fn empty_test_filter_lets_everything_through() {
+        for empty in &["", " ", ",,"] {
+            let filter = TestFilter::parse(empty).unwrap();
+            assert!(filter.is_all());
+            assert!(filter.matches(None));
+            assert!(filter.matches(Some(7)));
+        }
+        assert_eq!(TestFilter::all().to_string(), "all tests");
+    }
+
+    #[test]
+    fn bad_test_filters() {
+        for bad in &["20-18", "a", "1-2-3", "-5", "18-", "1.5"] {
+            assert!(TestFilter::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::escape;
+use regex::Regex;
+
+// What an export would change in a file that is already there, question by
+// question, so that yesterday's test isn't replaced without anyone noticing.
+// Works on Moodle XML, GIFT and Aiken files as this program writes them. Question
+// numbers are left out of the comparison, so a question that has only moved
+// counts as unchanged.
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub unchanged: usize,
+    // questions in both files with different answers, by their Latin
+    pub changed: Vec<String>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Summary {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+// A question as it is written in the file, without its number, and the
+// Latin it asks about
+struct Block {
+    text: String,
+    label: String,
+}
+
+// Split a file at each "<!-- question: n -->" or "// question: n" line.
+// Category headers are not questions and are left out.
+fn blocks(file: &str) -> Vec<Block> {
+    if !file.contains("<!-- question: ") && !file.contains("// question: ") {
+        return aiken_blocks(file);
+    }
+    let numbers = Regex::new(r"(question: |_q_|_match_)\d+").unwrap();
+    let mut pieces: Vec<Vec<&str>> = Vec::new();
+    for line in file.lines() {
+        if line.starts_with("<!-- question: ") || line.starts_with("// question: ") {
+            pieces.push(Vec::new());
+        }
+        if let Some(piece) = pieces.last_mut() {
+            if !line.starts_with("$CATEGORY") && line.trim() != "</quiz>" {
+                piece.push(line);
+            }
+        }
+    }
+    pieces
+        .into_iter()
+        .map(|lines| lines.join("\n"))
+        .filter(|text| !text.contains("<question type=\"category\">"))
+        .map(|text| Block {
+            label: label(&text),
+            text: numbers.replace_all(&text, "${1}#").trim().to_string(),
+        })
+        .collect()
+}
+
+// Aiken questions have no numbers, just a blank line after each one, and
+// start with their Latin
+fn aiken_blocks(file: &str) -> Vec<Block> {
+    file.split("\n\n")
+        .map(|text| text.trim())
+        .filter(|text| !text.is_empty())
+        .map(|text| Block {
+            label: text.lines().next().unwrap_or("").to_string(),
+            text: text.to_string(),
+        })
+        .collect()
+}
+
+// The Latin of a Cloze or GIFT question, or every Latin word of a matching one
+fn label(block: &str) -> String {
+    let paragraph = Regex::new(r"<p>(.*?)</p>").unwrap();
+    let gift = Regex::new(r"(?m)^::[^:]*::(.*) \{$").unwrap();
+    if block.contains("<question type=\"matching\">") {
+        let words = paragraph
+            .captures_iter(block)
+            .skip(1)
+            .map(|c| escape::unescape_xml_text(&c[1]))
+            .collect::<Vec<String>>();
+        return words.join(", ");
+    }
+    if let Some(c) = paragraph.captures(block) {
+        return escape::unescape_xml_text(&c[1]);
+    }
+    match gift.captures(block) {
+        Some(c) => c[1].replace('\\', ""),
+        None => String::new(),
+    }
+}
+
+pub fn compare(old: &str, new: &str) -> Summary {
+    let mut old_blocks = blocks(old);
+    let mut summary = Summary::default();
+    let mut added: Vec<Block> = Vec::new();
+    for block in blocks(new) {
+        match old_blocks.iter().position(|b| b.text == block.text) {
+            Some(position) => {
+                old_blocks.remove(position);
+                summary.unchanged += 1;
+            }
+            None => added.push(block),
+        }
+    }
+    // a question whose Latin is still there has been changed, not replaced
+    for block in added {
+        match old_blocks.iter().position(|b| b.label == block.label) {
+            Some(position) => {
+                old_blocks.remove(position);
+                summary.changed.push(block.label);
+            }
+            None => summary.added.push(block.label),
+        }
+    }
+    summary.removed = old_blocks.into_iter().map(|b| b.label).collect();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{question, written};
+    use crate::{gift, moodle, Category, Question};
+    use std::error::Error;
+    use std::path::Path;
+
+    type Writer = fn(&Path, &[Category]) -> Result<(), Box<dyn Error>>;
+
+    fn yesterday() -> Vec<Question> {
+        vec![
+            question("porto", "I carry"),
+            question("bonus", "good"),
+            question("hic", "here"),
+            question("et", "and"),
+        ]
+    }
+
+    // pro is added, et is removed, bonus has a new answer and the rest move
+    fn today() -> Vec<Question> {
+        vec![
+            question("hic", "here"),
+            question("porto", "I carry"),
+            question("bonus", "good, kind"),
+            question("pro", "instead of"),
+        ]
+    }
+
+    fn text_of(write: Writer, questions: &[Question], name: &str) -> String {
+        let (result, text) = written(&format!("compare_{}", name), |path| {
+            write(path, &[Category::single("top/A_20", "A_20", questions)])
+        });
+        result.unwrap();
+        text
+    }
+
+    fn check(write: Writer) {
+        let old = text_of(write, &yesterday(), "old");
+        let new = text_of(write, &today(), "new");
+        let summary = compare(&old, &new);
+        assert_eq!(summary.unchanged, 2);
+        assert_eq!(summary.changed, ["bonus"]);
+        assert_eq!(summary.added, ["pro"]);
+        assert_eq!(summary.removed, ["et"]);
+        assert!(!summary.is_empty());
+        assert!(compare(&old, &old).is_empty());
+        assert_eq!(compare(&old, &old).unchanged, 4);
+    }
+
+    #[test]
+    fn xml_changes() {
+        check(moodle::write_categories);
+    }
+
+    #[test]
+    fn gift_changes() {
+        check(|path, categories| gift::write_categories(path, categories).map(|_| ()));
+    }
+}
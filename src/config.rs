@@ -0,0 +1,154 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+// Settings that used to be written into the code: feedback, marks, the
+// category prefix and so on. They are read at start-up from a user file,
+// ~/.config/latin-vocab-test-maker/config.toml, and then a project file in
+// the current folder, whose settings win. Anything left out of both files
+// keeps its default.
+
+pub const PROJECT_FILE: &str = "./latin-vocab.toml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // offered when exporting, e.g. top/Vocabulary/A_ for top/Vocabulary/A_20
+    pub category_prefix: String,
+    // questions are numbered from the one after this
+    pub start_number: u32,
+    // the font of the Cloze gaps in Moodle XML
+    pub font: String,
+    // exports are named after their basename, e.g. ./A_20.xml, in the folder
+    // of these files. They are used as they are if there is no basename.
+    pub xml_file: String,
+    pub gift_file: String,
+    // feedback for every right answer
+    pub correct_feedback: String,
+    // the mark and feedback for the right verb in the wrong form
+    pub close_mark: u8,
+    pub close_feedback: String,
+    // the mark and feedback for another principal part of the verb,
+    // see build::WrongForms
+    pub wrong_form_mark: u8,
+    pub wrong_form_feedback: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            category_prefix: "top/Vocabulary/A_".to_string(),
+            start_number: 1000,
+            font: "times new roman,times,serif".to_string(),
+            xml_file: "./upload.xml".to_string(),
+            gift_file: "./upload.gift".to_string(),
+            correct_feedback: "Well done!".to_string(),
+            close_mark: 50,
+            close_feedback: "Close! What part of the verb is this?".to_string(),
+            wrong_form_mark: 0,
+            wrong_form_feedback: "That is the {given} — this is the {wanted}.".to_string(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref CONFIG: RwLock<Config> = RwLock::new(Config::default());
+}
+
+// The settings in use
+pub fn get() -> Config {
+    CONFIG.read().unwrap().clone()
+}
+
+pub fn correct_feedback() -> String {
+    CONFIG.read().unwrap().correct_feedback.clone()
+}
+
+pub fn set(config: Config) {
+    *CONFIG.write().unwrap() = config;
+}
+
+pub fn user_file() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("latin-vocab-test-maker").join("config.toml"))
+}
+
+// The settings in a file, or an empty table if there is no file
+fn read_table(path: &Path) -> Result<toml::value::Table, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(toml::value::Table::new());
+    }
+    let text = fs::read_to_string(path)?;
+    toml::from_str::<toml::value::Table>(&text)
+        .map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+// Read the user file and then the project file. A file that can't be read
+// is an error rather than being ignored, so a typo doesn't go unnoticed.
+pub fn load() -> Result<(), Box<dyn Error>> {
+    let user = match user_file() {
+        Some(path) => read_table(&path)?,
+        None => toml::value::Table::new(),
+    };
+    let project = read_table(Path::new(PROJECT_FILE))?;
+    set(merge(user, project)?);
+    Ok(())
+}
+
+// The project file's settings replace the user file's one key at a time,
+// and anything neither file has keeps its default
+fn merge(
+    mut user: toml::value::Table,
+    project: toml::value::Table,
+) -> Result<Config, Box<dyn Error>> {
+    for (key, value) in project {
+        user.insert(key, value);
+    }
+    Ok(toml::Value::Table(user).try_into()?)
+}
+
+// Write every setting to a file, making its folder if need be
+pub fn save(path: &Path, config: &Config) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string(config)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str) -> toml::value::Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn project_settings_win_key_by_key() {
+        let user = table("start_number = 2000\nfont = \"arial\"\nclose_mark = 40");
+        let project = table("start_number = 3000\nclose_feedback = \"Nearly!\"");
+        let config = merge(user, project).unwrap();
+        assert_eq!(config.start_number, 3000);
+        assert_eq!(config.font, "arial");
+        assert_eq!(config.close_mark, 40);
+        assert_eq!(config.close_feedback, "Nearly!");
+    }
+
+    #[test]
+    fn missing_settings_keep_their_defaults() {
+        let config = merge(table("wrong_form_mark = 10"), table("")).unwrap();
+        let default = Config::default();
+        assert_eq!(config.wrong_form_mark, 10);
+        assert_eq!(config.start_number, default.start_number);
+        assert_eq!(config.category_prefix, default.category_prefix);
+        assert_eq!(config.wrong_form_feedback, default.wrong_form_feedback);
+    }
+}
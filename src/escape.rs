@@ -0,0 +1,186 @@
+// Escaping for the two layers a Cloze question passes through on its way
+// into Moodle: the Cloze answer syntax itself, and the XML file around it.
+
+// Characters with a meaning inside {1:SHORTANSWER:...}, which Moodle
+// accepts literally when they are preceded by a backslash.
+const CLOZE_SPECIAL: [char; 6] = ['}', '#', '~', '/', '"', '\\'];
+
+pub fn cloze(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if CLOZE_SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub fn unescape_cloze(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) => unescaped.push(next),
+                None => unescaped.push(c),
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// Entity-escape text for an XML element or attribute, e.g. <text>...</text>
+pub fn xml_text(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn unescape_xml_text(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..]
+                .parse::<u32>()
+                .ok()
+                .and_then(std::char::from_u32),
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+// Text that goes inside <![CDATA[...]]> can hold anything except the
+// closing sequence, so split any "]]>" across two CDATA sections.
+pub fn cdata(s: &str) -> String {
+    s.replace("]]>", "]]]]><![CDATA[>")
+}
+
+pub fn unescape_cdata(s: &str) -> String {
+    s.replace("]]]]><![CDATA[>", "]]>")
+}
+
+// Strings that have caught out escaping before, for tests here and in the writers
+#[cfg(test)]
+pub(crate) const AWKWARD: [&str; 10] = [
+    "",
+    "I carry",
+    "instead of {ABL}",
+    "~%100%answer#feedback",
+    "and/or \"quoted\" \\ backslash",
+    "]]>",
+    "a]]>b]]]>c",
+    "<p>bonus &amp; bona</p>",
+    "&#x41; &unknown; & ;",
+    "portāre, Iūlius, ǣ",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A small deterministic generator so the tests need no extra crates
+    fn arbitrary_strings(count: usize) -> Vec<String> {
+        let alphabet: Vec<char> = "ab }#~/\"\\&<>';%]][[CDATA!āē\n".chars().collect();
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut strings = Vec::new();
+        for _ in 0..count {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            let len = (seed >> 59) as usize;
+            let mut s = String::new();
+            for _ in 0..len {
+                seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+                s.push(alphabet[(seed >> 33) as usize % alphabet.len()]);
+            }
+            strings.push(s);
+        }
+        strings
+    }
+
+    fn all_cases() -> Vec<String> {
+        let mut cases: Vec<String> = AWKWARD.iter().map(|s| s.to_string()).collect();
+        cases.extend(arbitrary_strings(500));
+        cases
+    }
+
+    #[test]
+    fn cloze_round_trips() {
+        for case in all_cases() {
+            assert_eq!(unescape_cloze(&cloze(&case)), case);
+        }
+    }
+
+    #[test]
+    fn cloze_leaves_no_bare_special_characters() {
+        for case in all_cases() {
+            let escaped = cloze(&case);
+            let mut chars = escaped.chars();
+            while let Some(c) = chars.next() {
+                if c == '\\' {
+                    assert!(CLOZE_SPECIAL.contains(&chars.next().unwrap()));
+                } else {
+                    assert!(!CLOZE_SPECIAL.contains(&c), "bare {:?} in {:?}", c, escaped);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn xml_text_round_trips() {
+        for case in all_cases() {
+            let escaped = xml_text(&case);
+            assert!(!escaped.contains('<') && !escaped.contains('>'));
+            assert_eq!(unescape_xml_text(&escaped), case);
+        }
+    }
+
+    #[test]
+    fn cdata_round_trips() {
+        for case in all_cases() {
+            let escaped = cdata(&case);
+            // every "]]>" left over closes a section that is immediately reopened
+            assert!(!escaped
+                .split("]]><![CDATA[")
+                .any(|section| section.contains("]]>")));
+            assert_eq!(unescape_cdata(&escaped), case);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::multichoice::Choices;
+use crate::{aiken, gift, matching, moodle, Category};
+use std::error::Error;
+use std::path::Path;
+
+// The formats a session can be exported to. Each one writes a list of
+// categories to a file; the export screen and batch mode pick one from
+// `exporters` rather than knowing about each format themselves.
+
+// Settings only some formats use
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    // words in each matching question
+    pub set_size: usize,
+    // options in each multiple choice question
+    pub choices: Choices,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            set_size: matching::DEFAULT_SET_SIZE,
+            choices: Choices::default(),
+        }
+    }
+}
+
+pub trait Exporter {
+    // for --format in batch mode
+    fn key(&self) -> &'static str;
+    // for the export menu
+    fn name(&self) -> &'static str;
+    fn extension(&self) -> &'static str;
+    fn uses_set_size(&self) -> bool {
+        false
+    }
+    fn uses_choices(&self) -> bool {
+        false
+    }
+    // Returns anything the user should be told, e.g. questions left out
+    fn write(
+        &self,
+        path: &Path,
+        categories: &[Category],
+        options: &ExportOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+pub fn exporters() -> Vec<Box<dyn Exporter>> {
+    vec![
+        Box::new(Cloze),
+        Box::new(Gift),
+        Box::new(Matching),
+        Box::new(MultiChoice),
+        Box::new(Aiken),
+    ]
+}
+
+pub fn find(key: &str) -> Option<Box<dyn Exporter>> {
+    exporters().into_iter().find(|e| e.key() == key)
+}
+
+// A note for questions an exporter had to leave out, if there were any
+fn left_out_note(left_out: usize, why: &str) -> Vec<String> {
+    match left_out {
+        0 => Vec::new(),
+        _ => vec![format!("{} {}", left_out, why)],
+    }
+}
+
+fn typed_gaps_note(typed_gaps: usize) -> Vec<String> {
+    left_out_note(
+        typed_gaps,
+        "questions with several typed gaps, like principal parts, were left out.",
+    )
+}
+
+struct Cloze;
+
+impl Exporter for Cloze {
+    fn key(&self) -> &'static str {
+        "xml"
+    }
+    fn name(&self) -> &'static str {
+        "Moodle XML (Cloze)"
+    }
+    fn extension(&self) -> &'static str {
+        "xml"
+    }
+    fn write(
+        &self,
+        path: &Path,
+        categories: &[Category],
+        _options: &ExportOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        moodle::write_categories(path, categories)?;
+        Ok(Vec::new())
+    }
+}
+
+struct Gift;
+
+impl Exporter for Gift {
+    fn key(&self) -> &'static str {
+        "gift"
+    }
+    fn name(&self) -> &'static str {
+        "GIFT (short answer)"
+    }
+    fn extension(&self) -> &'static str {
+        "gift"
+    }
+    fn write(
+        &self,
+        path: &Path,
+        categories: &[Category],
+        _options: &ExportOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let typed_gaps = gift::write_categories(path, categories)?;
+        Ok(typed_gaps_note(typed_gaps))
+    }
+}
+
+struct Matching;
+
+impl Exporter for Matching {
+    fn key(&self) -> &'static str {
+        "matching"
+    }
+    fn name(&self) -> &'static str {
+        "Moodle XML (matching sets)"
+    }
+    fn extension(&self) -> &'static str {
+        "xml"
+    }
+    fn uses_set_size(&self) -> bool {
+        true
+    }
+    fn write(
+        &self,
+        path: &Path,
+        categories: &[Category],
+        options: &ExportOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let left_out = matching::write_categories(path, categories, options.set_size)?;
+        let mut notes = left_out_note(
+            left_out.clashed,
+            "words would have clashed in every set and were left out.",
+        );
+        notes.extend(left_out_note(
+            left_out.too_few,
+            "words were left out as there were too few to make up another set.",
+        ));
+        Ok(notes)
+    }
+}
+
+struct MultiChoice;
+
+impl Exporter for MultiChoice {
+    fn key(&self) -> &'static str {
+        "multichoice"
+    }
+    fn name(&self) -> &'static str {
+        "Moodle XML (multiple choice)"
+    }
+    fn extension(&self) -> &'static str {
+        "xml"
+    }
+    fn uses_choices(&self) -> bool {
+        true
+    }
+    fn write(
+        &self,
+        path: &Path,
+        categories: &[Category],
+        options: &ExportOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let left_out = moodle::write_multichoice_categories(path, categories, &options.choices)?;
+        let mut notes = left_out_note(
+            left_out.no_menu,
+            "questions had no wrong options to offer and were left out.",
+        );
+        notes.extend(typed_gaps_note(left_out.typed_gaps));
+        Ok(notes)
+    }
+}
+
+struct Aiken;
+
+impl Exporter for Aiken {
+    fn key(&self) -> &'static str {
+        "aiken"
+    }
+    fn name(&self) -> &'static str {
+        "Aiken (multiple choice)"
+    }
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+    fn uses_choices(&self) -> bool {
+        true
+    }
+    fn write(
+        &self,
+        path: &Path,
+        categories: &[Category],
+        options: &ExportOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let left_out = aiken::write_categories(path, categories, &options.choices)?;
+        let mut notes = left_out_note(
+            left_out.no_menu,
+            "questions needed one right answer and some wrong ones, and were left out.",
+        );
+        notes.extend(typed_gaps_note(left_out.typed_gaps));
+        Ok(notes)
+    }
+}
@@ -0,0 +1,163 @@
+use crate::config;
+use crate::multichoice;
+use crate::{AnswerOption, Category, GapType};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Moodle's GIFT format, see https://docs.moodle.org/en/GIFT_format
+// Each question is written as a short answer question:
+//
+// ::A_20_q_1001::porto {
+//     =%100%I carry#Well done!
+//     =%50%*carry*#Close! What part of the verb is this?
+// }
+//
+// GIFT has one set of answers per question, so a question's menu gaps are
+// written as questions of their own, as for multiple choice. Returns the
+// number of questions left out because they had typed extra gaps.
+
+pub fn write_categories(path: &Path, categories: &[Category]) -> Result<usize, Box<dyn Error>> {
+    let f = File::create(path)?;
+    let mut writer = BufWriter::new(f);
+    let mut question_number: u32 = config::get().start_number;
+    let mut left_out = 0;
+    for category in categories {
+        // category paths are not escaped: Moodle reads the rest of the line as is
+        writeln!(writer, "$CATEGORY: $course$/{}\n", category.path)?;
+        let (questions, typed_gaps) = multichoice::one_gap_each(&category.questions);
+        left_out += typed_gaps;
+        for question in questions.iter() {
+            question_number += 1;
+            let question_name = format!("{}_q_{}", category.basename, question_number);
+            writeln!(writer, "// question: {}", question_number)?;
+            let answers = match question.gap_type {
+                GapType::ShortAnswer => gift_shortanswer(&question.answers),
+                GapType::MultiChoice => gift_multichoice(&question.answers),
+            };
+            writeln!(
+                writer,
+                "::{}::{} {{\n{}}}\n",
+                escape(&question_name),
+                escape(&question.latin),
+                answers
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(left_out)
+}
+
+// In a short answer question every answer starts with '=', and the
+// percentage sets the credit it earns.
+pub fn gift_shortanswer(answers: &[AnswerOption]) -> String {
+    let mut answer_string = String::new();
+    for answer in answers {
+        answer_string.push_str(&format!("    =%{}%{}", answer.mark, escape(&answer.answer)));
+        if !answer.feedback.is_empty() {
+            answer_string.push_str(&format!("#{}", escape(&answer.feedback)));
+        }
+        answer_string.push('\n');
+    }
+    answer_string
+}
+
+// In a multiple choice question the right answer starts with '=' and
+// the others with '~', with a percentage if they earn partial credit.
+pub fn gift_multichoice(answers: &[AnswerOption]) -> String {
+    let mut answer_string = String::new();
+    for answer in answers {
+        let prefix = match answer.mark {
+            100 => "=".to_string(),
+            0 => "~".to_string(),
+            mark => format!("~%{}%", mark),
+        };
+        answer_string.push_str(&format!("    {}{}", prefix, escape(&answer.answer)));
+        if !answer.feedback.is_empty() {
+            answer_string.push_str(&format!("#{}", escape(&answer.feedback)));
+        }
+        answer_string.push('\n');
+    }
+    answer_string
+}
+
+// Backslash-escape the characters GIFT gives a special meaning to
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '~' | '=' | '#' | '{' | '}' | ':' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{question, written};
+    use crate::Question;
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(escape("I carry"), "I carry");
+        assert_eq!(escape("~%100%a=b#c"), "\\~%100%a\\=b\\#c");
+        assert_eq!(escape("{ABL}: \\"), "\\{ABL\\}\\: \\\\");
+        assert_eq!(escape("long\nand tall"), "long\\nand tall");
+    }
+
+    #[test]
+    fn short_answer_and_multichoice_questions() {
+        let carry = question("porto", "I carry");
+        let gender = Question {
+            gap_type: GapType::MultiChoice,
+            ..Question::new(
+                "civis: which gender?".to_string(),
+                vec![
+                    AnswerOption {
+                        mark: 100,
+                        answer: "masculine".to_string(),
+                        feedback: "Yes".to_string(),
+                    },
+                    AnswerOption {
+                        mark: 50,
+                        answer: "feminine".to_string(),
+                        feedback: "It can be, but usually isn't".to_string(),
+                    },
+                    AnswerOption {
+                        mark: 0,
+                        answer: "neuter".to_string(),
+                        feedback: String::new(),
+                    },
+                ],
+            )
+        };
+        let questions = [carry, gender];
+        let (left_out, text) = written("gift_questions.gift", |path| {
+            write_categories(path, &[Category::single("top/A_20", "A_20", &questions)]).unwrap()
+        });
+        assert_eq!(left_out, 0);
+        let first = config::get().start_number + 1;
+        let expected = format!(
+            "$CATEGORY: $course$/top/A_20\n\n\
+             // question: {}\n\
+             ::A_20_q_{}::porto {{\n    =%100%I carry\n}}\n\n\
+             // question: {}\n\
+             ::A_20_q_{}::civis\\: which gender? {{\n    \
+             =masculine#Yes\n    \
+             ~%50%feminine#It can be, but usually isn't\n    \
+             ~neuter\n}}\n\n",
+            first,
+            first,
+            first + 1,
+            first + 1
+        );
+        assert_eq!(text, expected);
+    }
+}
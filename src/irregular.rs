@@ -0,0 +1,267 @@
+use crate::spelling;
+use crate::verb_cache;
+
+// Irregular verbs whose principal parts don't follow the usual pattern of
+// present, infinitive, perfect, perfect participle, or whose English
+// can't be built by looking the verb up: sum, possum, eo, fero, volo,
+// nolo, malo and their compounds (adsum, redeo, aufero...).
+
+// What each principal part of an irregular verb is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartKind {
+    Present,
+    Infinitive,
+    Perfect,
+    PerfectParticiple,
+    FutureParticiple,
+    Supine,
+}
+
+impl PartKind {
+    // How feedback names the part, e.g. "That is the present tense"
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartKind::Present => "present tense",
+            PartKind::Infinitive => "infinitive",
+            PartKind::Perfect => "perfect tense",
+            PartKind::PerfectParticiple => "perfect participle",
+            PartKind::FutureParticiple => "future participle",
+            PartKind::Supine => "supine",
+        }
+    }
+}
+
+// A principal part and the English answers for it
+#[derive(Debug, Clone)]
+pub struct PrincipalPart {
+    pub latin: String,
+    pub kind: PartKind,
+    pub english: Vec<String>,
+}
+
+// English for the verbs that can't be built from the vocab list's meaning
+struct FixedEnglish {
+    latin: &'static str,
+    present: &'static [&'static str],
+    infinitive: &'static [&'static str],
+    perfect: &'static [&'static str],
+}
+
+const FIXED: [FixedEnglish; 3] = [
+    FixedEnglish {
+        latin: "possum",
+        present: &["I am able", "I can"],
+        infinitive: &["to be able"],
+        perfect: &["I was able", "I could", "I have been able"],
+    },
+    FixedEnglish {
+        latin: "nolo",
+        present: &["I do not want", "I don't want", "I am unwilling"],
+        infinitive: &["not to want", "to not want", "to be unwilling"],
+        perfect: &["I did not want", "I didn't want", "I was unwilling"],
+    },
+    FixedEnglish {
+        latin: "malo",
+        present: &["I prefer", "I would rather"],
+        infinitive: &["to prefer"],
+        perfect: &["I preferred"],
+    },
+];
+
+const SUM_PARTS: [PartKind; 4] = [
+    PartKind::Present,
+    PartKind::Infinitive,
+    PartKind::Perfect,
+    PartKind::FutureParticiple,
+];
+const EO_PARTS: [PartKind; 4] = [
+    PartKind::Present,
+    PartKind::Infinitive,
+    PartKind::Perfect,
+    PartKind::Supine,
+];
+const FERO_PARTS: [PartKind; 4] = [
+    PartKind::Present,
+    PartKind::Infinitive,
+    PartKind::Perfect,
+    PartKind::PerfectParticiple,
+];
+
+// A part without macrons, to compare with the tables above, e.g. redeō -> redeo
+fn plain(part: &str) -> String {
+    spelling::strip_macrons(part.trim()).to_lowercase()
+}
+
+// Work out which irregular verb, if any, the principal parts belong to
+// from the first two parts, e.g. redeo, redire is a compound of eo.
+fn part_kinds(latin_parts: &[&str]) -> Option<&'static [PartKind]> {
+    let first = plain(latin_parts.first()?);
+    let second = latin_parts.get(1).map(|p| plain(p)).unwrap_or_default();
+    let compound_of = |ending: &str, infinitive: &str| match first.strip_suffix(ending) {
+        Some(prefix) => second == format!("{}{}", prefix, infinitive),
+        None => false,
+    };
+    // only with their own infinitive: volo, volare is the regular "fly"
+    let wanting = [("volo", "velle"), ("nolo", "nolle"), ("malo", "malle")];
+    if wanting.contains(&(first.as_str(), second.as_str())) {
+        return Some(&SUM_PARTS[..3]);
+    }
+    // the infinitive isn't always prefix + esse: possum, posse and prosum, prodesse
+    if first == "sum" || first == "possum" || (first.ends_with("sum") && second.ends_with("esse")) {
+        return Some(&SUM_PARTS);
+    }
+    if compound_of("eo", "ire") {
+        return Some(&EO_PARTS);
+    }
+    if compound_of("fero", "ferre") {
+        return Some(&FERO_PARTS);
+    }
+    None
+}
+
+// English answers for each principal part, or None if the verb is not irregular.
+// `meanings` are the English meanings from the vocab list with the "I " removed,
+// e.g. ["am present"] for adsum or ["return", "go back"] for redeo.
+pub fn principal_parts(latin_parts: &[&str], meanings: &[&str]) -> Option<Vec<PrincipalPart>> {
+    let kinds = part_kinds(latin_parts)?;
+    let first = plain(latin_parts[0]);
+    let fixed = FIXED.iter().find(|f| f.latin == first);
+    let mut parts: Vec<PrincipalPart> = Vec::new();
+    for (latin, kind) in latin_parts.iter().zip(kinds.iter()) {
+        let english = match fixed {
+            Some(fixed) => fixed_english(fixed, *kind),
+            None => meanings
+                .iter()
+                .map(|m| m.trim())
+                .filter(|m| !m.is_empty())
+                .flat_map(|m| english_for(m, *kind))
+                .collect(),
+        };
+        parts.push(PrincipalPart {
+            latin: latin.trim().to_string(),
+            kind: *kind,
+            english,
+        });
+    }
+    Some(parts)
+}
+
+fn fixed_english(fixed: &FixedEnglish, kind: PartKind) -> Vec<String> {
+    let forms: &[&str] = match kind {
+        PartKind::Present => fixed.present,
+        PartKind::Infinitive => fixed.infinitive,
+        PartKind::Perfect => fixed.perfect,
+        _ => &[],
+    };
+    forms.iter().map(|f| f.to_string()).collect()
+}
+
+// English for one meaning, e.g. "am present" or "carry off"
+fn english_for(meaning: &str, kind: PartKind) -> Vec<String> {
+    // "be" can't be looked up, so sum and its compounds are done by hand
+    let complement = match meaning {
+        "am" | "be" => Some(""),
+        _ => meaning
+            .strip_prefix("am ")
+            .or_else(|| meaning.strip_prefix("be ")),
+    };
+    let forms = match complement {
+        Some(rest) => match kind {
+            PartKind::Present => vec![format!("I am {}", rest)],
+            PartKind::Infinitive => vec![format!("to be {}", rest)],
+            PartKind::Perfect => vec![format!("I was {}", rest), format!("I have been {}", rest)],
+            PartKind::FutureParticiple => vec![format!("about to be {}", rest)],
+            _ => vec![],
+        },
+        None => {
+            // only the verb itself is looked up, the rest of the phrase is kept
+            let mut words = meaning.splitn(2, ' ');
+            let head = words.next().unwrap_or("");
+            let rest = words.next().unwrap_or("");
+            let verb = verb_cache::lookup(head);
+            match kind {
+                PartKind::Present => vec![format!("I {} {}", verb.present, rest)],
+                PartKind::Infinitive => vec![format!("to {} {}", verb.present, rest)],
+                PartKind::Perfect => vec![format!("I {} {}", verb.past_simple, rest)],
+                PartKind::PerfectParticiple => {
+                    vec![format!("having been {} {}", verb.past_part, rest)]
+                }
+                PartKind::FutureParticiple => vec![format!("about to {} {}", verb.present, rest)],
+                PartKind::Supine => vec![
+                    format!("to {} {}", verb.present, rest),
+                    format!("in order to {} {}", verb.present, rest),
+                ],
+            }
+        }
+    };
+    forms.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volo_want_is_irregular() {
+        assert_eq!(
+            part_kinds(&["volo", " velle", " volui"]),
+            Some(&SUM_PARTS[..3])
+        );
+        let parts = principal_parts(&["nōlo", " nōlle", " nōlui"], &["not want"]).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(parts[0].english.contains(&"I do not want".to_string()));
+    }
+
+    #[test]
+    fn volo_fly_is_regular() {
+        let latin = ["volo", " volare", " volavi", " volatum"];
+        assert!(part_kinds(&latin).is_none());
+        assert!(principal_parts(&latin, &["fly"]).is_none());
+    }
+
+    fn english(part: &PrincipalPart) -> Vec<&str> {
+        part.english.iter().map(|e| e.as_str()).collect()
+    }
+
+    #[test]
+    fn sum_has_a_future_participle() {
+        let parts = principal_parts(&["sum", " esse", " fui", " futūrus"], &["am"]).unwrap();
+        assert_eq!(parts[3].latin, "futūrus");
+        assert_eq!(parts[3].kind, PartKind::FutureParticiple);
+        assert_eq!(english(&parts[3]), ["about to be"]);
+        assert_eq!(english(&parts[2]), ["I was", "I have been"]);
+    }
+
+    #[test]
+    fn possum_has_fixed_english() {
+        let parts = principal_parts(&["possum", " posse", " potui"], &["can"]).unwrap();
+        assert_eq!(english(&parts[0]), ["I am able", "I can"]);
+        assert_eq!(english(&parts[1]), ["to be able"]);
+        assert_eq!(
+            english(&parts[2]),
+            ["I was able", "I could", "I have been able"]
+        );
+    }
+
+    #[test]
+    fn redeo_has_a_supine() {
+        verb_cache::add_override("return", "return", "returned", "returned");
+        let parts =
+            principal_parts(&["redeo", " redire", " redii", " reditum"], &["return"]).unwrap();
+        assert_eq!(parts[3].kind, PartKind::Supine);
+        assert_eq!(english(&parts[3]), ["to return", "in order to return"]);
+    }
+
+    #[test]
+    fn aufero_has_a_perfect_participle() {
+        verb_cache::add_override("carry", "carry", "carried", "carried");
+        let parts = principal_parts(
+            &["aufero", " auferre", " abstuli", " ablatus"],
+            &["carry off"],
+        )
+        .unwrap();
+        assert_eq!(parts[3].kind, PartKind::PerfectParticiple);
+        assert_eq!(english(&parts[3]), ["having been carried off"]);
+        assert_eq!(english(&parts[2]), ["I carried off"]);
+    }
+}
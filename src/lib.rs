@@ -1,11 +1,58 @@
 use ncurses::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 // use std::thread::LocalKey;
 
-#[derive(Debug)]
+pub mod adjective;
+pub mod aiken;
+pub mod build;
+pub mod compare;
+pub mod config;
+pub mod escape;
+pub mod export;
+pub mod gift;
+pub mod irregular;
+pub mod matching;
+pub mod moodle;
+pub mod multichoice;
+pub mod pos;
+pub mod reverse;
+pub mod session;
+pub mod spelling;
+pub mod verb_cache;
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Question {
     pub latin: String,
     pub answers: Vec<AnswerOption>,
+    // the value of the vocab file's Test column, if it had one
+    #[serde(default)]
+    pub test: Option<u32>,
+    // how the answers are offered: typed in, or picked from a list
+    #[serde(default)]
+    pub gap_type: GapType,
+    // further gaps after the first, e.g. "+ which case?" for a preposition
+    #[serde(default)]
+    pub extra_gaps: Vec<Gap>,
+    // an English -> Latin question, made by reverse::reverse_questions,
+    // so it isn't turned round again
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum GapType {
+    #[default]
+    ShortAnswer,
+    MultiChoice,
+}
+
+// A gap that follows the question's first one, with the text shown before it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gap {
+    pub prompt: String,
+    pub gap_type: GapType,
+    pub answers: Vec<AnswerOption>,
 }
 
 impl Clone for Question {
@@ -16,11 +63,40 @@ impl Clone for Question {
             let ao_dup = answer.clone();
             answers.push(ao_dup);
         }
-        Question { latin, answers }
+        Question {
+            latin,
+            answers,
+            test: self.test,
+            gap_type: self.gap_type,
+            extra_gaps: self.extra_gaps.clone(),
+            reversed: self.reversed,
+        }
+    }
+}
+
+impl Question {
+    // A typed question with nothing else to it yet
+    pub fn new(latin: String, answers: Vec<AnswerOption>) -> Question {
+        Question {
+            latin,
+            answers,
+            test: None,
+            gap_type: GapType::ShortAnswer,
+            extra_gaps: Vec::new(),
+            reversed: false,
+        }
+    }
+
+    // A question with more than one typed gap, like a verb's principal parts,
+    // can't be turned round, made multiple choice or put in a matching set
+    pub fn has_typed_extra_gaps(&self) -> bool {
+        self.extra_gaps
+            .iter()
+            .any(|gap| gap.gap_type == GapType::ShortAnswer)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AnswerOption {
     pub mark: u8,
     pub answer: String,
@@ -47,6 +123,60 @@ pub struct Record {
     #[serde(rename = "Part of Speech")]
     pub part_of_speech: String,
     pub english: String,
+    #[serde(default)]
+    pub test: Option<u32>,
+}
+
+// A group of questions that is exported into one question bank category
+pub struct Category<'a> {
+    pub path: String,
+    pub basename: String,
+    pub questions: Vec<&'a Question>,
+}
+
+impl<'a> Category<'a> {
+    pub fn single(path: &str, basename: &str, questions: &'a [Question]) -> Category<'a> {
+        Category {
+            path: path.to_string(),
+            basename: basename.to_string(),
+            questions: questions.iter().collect(),
+        }
+    }
+
+    // One category per value of the Test column, e.g. with the prefix
+    // top/Vocabulary/A_ the questions from test 20 go into top/Vocabulary/A_20.
+    // Questions without a test number go into the prefix category itself.
+    pub fn by_test(prefix: &str, questions: &'a [Question]) -> Vec<Category<'a>> {
+        let mut by_test: BTreeMap<Option<u32>, Vec<&Question>> = BTreeMap::new();
+        for question in questions {
+            by_test.entry(question.test).or_default().push(question);
+        }
+        by_test
+            .into_iter()
+            .map(|(test, questions)| {
+                let path = match test {
+                    Some(num) => format!("{}{}", prefix, num),
+                    None => prefix.to_string(),
+                };
+                let basename = basename_for(&path);
+                Category {
+                    path,
+                    basename,
+                    questions,
+                }
+            })
+            .collect()
+    }
+}
+
+// The name of the last folder in a category path, e.g. A_20 for top/Vocabulary/A_20
+pub fn basename_for(category: &str) -> String {
+    let name = category
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or("");
+    name.trim_end_matches('_').to_string()
 }
 
 pub fn clear_win(win: WINDOW) {
@@ -76,3 +206,37 @@ pub fn prompt() {
     refresh();
     getch();
 }
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use super::{AnswerOption, Question};
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // A question with one right answer
+    pub(crate) fn question(latin: &str, english: &str) -> Question {
+        Question::new(
+            latin.to_string(),
+            vec![AnswerOption {
+                mark: 100,
+                answer: english.to_string(),
+                feedback: String::new(),
+            }],
+        )
+    }
+
+    // A file in the temp directory that no other test run will use
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("{}_{}", name, std::process::id()))
+    }
+
+    // Whatever write returned, and the text it wrote
+    pub(crate) fn written<T>(name: &str, write: impl FnOnce(&Path) -> T) -> (T, String) {
+        let path = temp_path(name);
+        let result = write(&path);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (result, text)
+    }
+}
@@ -1,18 +1,21 @@
-use csv::Reader;
-// use english_past::{lookup, Verb};
+use latin_vocab_test_maker::build::{
+    apply_direction, build_word, import_file, ImportError, ImportOptions, TestFilter, VerbStrategy,
+    WrongForms,
+};
+use latin_vocab_test_maker::export::{self, ExportOptions};
+use latin_vocab_test_maker::multichoice::Choices;
+use latin_vocab_test_maker::pos::PartOfSpeech;
+use latin_vocab_test_maker::reverse::{Direction, Marking};
 use latin_vocab_test_maker::*;
-use mw_past::{lookup, Verb};
+use latin_vocab_test_maker::{compare, config, matching, moodle, session, spelling, verb_cache};
 use ncurses::*;
-use regex::Regex;
+use std::cell::RefCell;
 use std::char;
+use std::env;
 use std::error::Error;
-use std::fs::read_dir;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::fs::{self, read_dir};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::mpsc;
-use std::thread;
 
 // declare windows as global variables
 thread_local!(
@@ -20,20 +23,179 @@ thread_local!(
     pub static MAIN_WIN: WINDOW = newwin(getmaxy(stdscr()) - 10, 80, 5, 0);
     pub static KEYS_WIN: WINDOW = newwin(3, 80, getmaxy(stdscr()) - 5, 0);
     pub static INPUT_WIN: WINDOW = newwin(2, 80, getmaxy(stdscr()) - 2, 0);
+    // the session file currently being worked on, if it has been saved or opened
+    pub static SESSION_FILE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
 );
 
+const USAGE: &str = "Usage:\n    \
+    latin-vocab-test-maker\n    \
+    latin-vocab-test-maker batch <vocab.csv> <category> <basename> <output.xml> [options]\n\n\
+Options:\n    \
+    --tests <list>             only import these tests, e.g. 20, 18-20 or 18,19,22\n    \
+    --per-test                 write one category per test, using <category> as the prefix\n    \
+    --format <fmt>             xml (Moodle XML Cloze, the default), gift, matching,\n    \
+                               multichoice (Moodle XML) or aiken\n    \
+    --set-size <n>             words in each matching question (default 5)\n    \
+    --noun-parts               separate meaning, genitive and gender questions for nouns\n    \
+    --comparison               comparative and superlative questions for adjectives\n    \
+    --principal-parts          one question per verb with a gap for each principal part\n    \
+    --wrong-form-mark <n>      mark for giving another principal part (default 0)\n    \
+    --wrong-form-feedback <s>  feedback for it, with {given} and {wanted}\n    \
+    --direction <d>            la-en (the default), en-la or both\n    \
+    --multichoice <n>          la-en menus of n options instead of typed answers\n    \
+    --seed <n>                 seed for the order of multichoice options (default 1)\n    \
+    --no-macrons               leave the macrons off the Latin of la-en questions\n    \
+    --macron-mark <n>          mark for en-la answers typed with macrons (default 100)\n    \
+    --plain-mark <n>           mark for en-la answers typed without macrons (default 100)\n    \
+    --stem-mark <n>            mark for en-la answers with the wrong ending (default 50)\n    \
+    --no-ij                    don't accept j for consonantal i in en-la answers\n    \
+    --no-uv                    don't accept v for consonantal u in en-la answers";
+
 fn main() {
-    if let Err(e) = run() {
+    let args: Vec<String> = env::args().collect();
+    if let Err(e) = config::load() {
+        println!("Unable to read settings: {}", e);
+        process::exit(1);
+    }
+    let result = match args.get(1).map(|a| a.as_str()) {
+        None => run(),
+        Some("batch") => batch(&args[2..]),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(other) => Err(format!("unknown command '{}'\n{}", other, USAGE).into()),
+    };
+    if let Err(e) = result {
         println!("Application error: {}", e);
         process::exit(1);
     }
 }
 
+// Build a test without the ncurses interface, e.g. from a Makefile:
+// latin-vocab-test-maker batch ch20-vocab top/Vocabulary/A_20 A_20 A_20.xml
+fn batch(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut positional: Vec<&String> = Vec::new();
+    let mut options = ImportOptions::default();
+    let mut per_test = false;
+    let mut format = String::from("xml");
+    let mut set_size = matching::DEFAULT_SET_SIZE;
+    // only --multichoice turns menus on; the seed is kept until the end
+    let mut seed: Option<u64> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tests" => {
+                let list = args.next().ok_or("--tests needs a list of tests")?;
+                options.tests = TestFilter::parse(list)?;
+            }
+            "--per-test" => per_test = true,
+            "--noun-parts" => options.noun_parts = true,
+            "--comparison" => options.comparison = true,
+            "--principal-parts" => options.verbs = VerbStrategy::PrincipalParts,
+            "--direction" => {
+                options.direction = match args.next().map(|d| d.as_str()) {
+                    Some("la-en") => Direction::LatinToEnglish,
+                    Some("en-la") => Direction::EnglishToLatin,
+                    Some("both") => Direction::Both,
+                    _ => return Err("--direction needs la-en, en-la or both".into()),
+                }
+            }
+            "--multichoice" | "--seed" => {
+                let num = args
+                    .next()
+                    .and_then(|n| n.parse::<u64>().ok())
+                    .ok_or(format!("{} needs a number", arg))?;
+                match arg.as_str() {
+                    "--multichoice" if num < 2 => {
+                        return Err("--multichoice needs at least 2 options".into())
+                    }
+                    "--multichoice" => {
+                        options.choices.get_or_insert_with(Choices::default).count = num as usize
+                    }
+                    _ => seed = Some(num),
+                }
+            }
+            "--no-macrons" => options.macrons = false,
+            "--wrong-form-feedback" => {
+                options.wrong_forms.feedback = args
+                    .next()
+                    .ok_or("--wrong-form-feedback needs some feedback")?
+                    .to_string();
+            }
+            "--macron-mark" | "--plain-mark" | "--stem-mark" | "--wrong-form-mark" => {
+                let mark = args
+                    .next()
+                    .and_then(|m| m.parse::<u8>().ok())
+                    .filter(|m| *m <= 100)
+                    .ok_or(format!("{} needs a mark from 0 to 100", arg))?;
+                match arg.as_str() {
+                    "--macron-mark" => options.marking.macron_mark = mark,
+                    "--plain-mark" => options.marking.plain_mark = mark,
+                    "--wrong-form-mark" => options.wrong_forms.mark = mark,
+                    _ => options.marking.stem_mark = mark,
+                }
+            }
+            "--no-ij" => options.marking.ij = false,
+            "--no-uv" => options.marking.uv = false,
+            "--set-size" => {
+                set_size = args
+                    .next()
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|n| *n >= matching::MIN_SET_SIZE)
+                    .ok_or(format!(
+                        "--set-size needs a number of at least {}",
+                        matching::MIN_SET_SIZE
+                    ))?;
+            }
+            "--format" => {
+                format = args.next().ok_or("--format needs a format")?.to_string();
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 4 {
+        return Err(format!("batch needs 4 arguments\n{}", USAGE).into());
+    }
+    if let (Some(choices), Some(seed)) = (options.choices.as_mut(), seed) {
+        choices.seed = seed;
+    }
+    let (vocab, category, basename, output) =
+        (positional[0], positional[1], positional[2], positional[3]);
+    let import = import_file(Path::new(vocab), &options, &|| {})?;
+    for skipped in &import.skipped {
+        eprintln!("skipped {}", skipped);
+    }
+    let questions = import.questions;
+    let exporter = export::find(&format).ok_or(format!("unknown format '{}'", format))?;
+    let mut export_options = ExportOptions {
+        set_size,
+        choices: options.choices.clone().unwrap_or_default(),
+    };
+    // the multichoice and Aiken formats shuffle their options too
+    if let Some(seed) = seed {
+        export_options.choices.seed = seed;
+    }
+    let categories = match per_test {
+        true => Category::by_test(category, &questions),
+        false => vec![Category::single(category, basename, &questions)],
+    };
+    for note in exporter.write(Path::new(output), &categories, &export_options)? {
+        eprintln!("{}", note);
+    }
+    for problem in verb_cache::problems() {
+        eprintln!("{}: {}", problem.english, problem.reason);
+    }
+    eprintln!("{} questions written to {}", questions.len(), output);
+    Ok(())
+}
+
 fn run() -> Result<(), Box<dyn Error>> {
     let mut questions: Vec<Question> = Vec::new();
     setlocale(ncurses::constants::LcCategory::all, "utf8");
     initscr();
     noecho();
+    recover_autosave(&mut questions);
     loop {
         // clear the screen
         clear();
@@ -62,8 +224,12 @@ fn run() -> Result<(), Box<dyn Error>> {
                 *main_win,
                 "Main menu:\n\n\
                            Select an option from the list below.\n\n\
-                           - Add: Enter a question manually. Good luck!\n\n\
-                           - Import: Create questions from words in a file.",
+                           - Add: Enter a question manually. Good luck!\n\
+                           - Import: Create questions from a vocab list, or read\n\
+                             a Moodle XML question bank back in.\n\
+                           - Open/Save: Load or save a session file.\n\
+                           - Lookups: Verbs whose English forms could not be found.\n\
+                           - Config: Feedback, marks and other settings.",
             );
         });
 
@@ -75,17 +241,32 @@ fn run() -> Result<(), Box<dyn Error>> {
                 *keys_win,
                 1,
                 1,
-                "a: add    i: import    r: review    x: export    s: start again    q: quit",
+                "a:add i:import r:review x:export o:open w:save l:lookups c:config s:new q:quit",
             );
             wrefresh(*keys_win);
         });
 
         match char::from_u32(getch() as u32).unwrap() {
-            'a' => unimplemented!(),
-            'i' => import(&mut questions),
+            'a' => {
+                add_question(&mut questions);
+                autosave(&questions);
+            }
+            'i' => {
+                import(&mut questions);
+                autosave(&questions);
+            }
             'r' => pager(&mut questions),
             'x' => export(&questions),
-            's' => questions.clear(),
+            'o' => open_session(&mut questions),
+            'l' => lookup_problems(),
+            'c' => settings(),
+            'w' => save_session(&questions),
+            's' => {
+                if questions.is_empty() || confirm("Clear all the questions? (y/n) ") {
+                    questions.clear();
+                    autosave(&questions);
+                }
+            }
             'q' => break,
             _ => continue,
         }
@@ -94,155 +275,667 @@ fn run() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn export(questions: &Vec<Question>) {
-    // Put opening statement in xml file
-    // fields are stage_number, folder_name
-    macro_rules! xml_start {
-        ($arg1:expr) => {
-            format!(
-                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
-             <quiz>\n\
-             <!-- question: 0  -->\n\
-             <question type=\"category\">\n\
-             <category>\n\
-             <text>$course$/{}</text>\n\
-             </category>\n\
-             </question>\n",
-                $arg1
-            )
-            .as_bytes()
-        };
-    }
-
-    // fields are question_number, question_name, question, questioncode
-    macro_rules! xml_question {
-        ($q_num:expr, $q_name:expr, $question:expr, $q_code:expr) => {
-            format!(
-                "<!-- question: {}  -->\n\
-                 <question type=\"cloze\" > \n\
-                 <name>\n\
-                 <text>{}</text>\n\
-                 </name>\n\
-                 <questiontext>\n\
-                 <text>\n\
-                 <![CDATA[<p>{}</p>\n\
-                 <p><font size=\"4\" face=\"times new roman,times,serif\">{}.</font></p>]]>\n\
-                 </text>\n\
-                 </questiontext>\n\
-                 <generalfeedback>\n\
-                 <text></text>\n\
-                 </generalfeedback>\n\
-                 <shuffleanswers>0</shuffleanswers>\n\
-                 </question>\n",
-                $q_num, $q_name, $question, $q_code
-            ).as_bytes()
-        }
-    }
-
-    let xml_end: String = String::from("</quiz>\n");
-
+fn export(questions: &[Question]) {
+    let exporters = export::exporters();
+    let mut menu = String::from("Choose an export format.");
+    for (num, exporter) in exporters.iter().enumerate() {
+        menu.push_str(&format!("\n{}. {}", num + 1, exporter.name()));
+    }
+    MAIN_WIN.with(|main_win| overwrite_win(*main_win, &menu));
+    let choice = get_input("Which format? ")
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n >= 1 && *n <= exporters.len())
+        .unwrap_or(1);
+    let exporter = &exporters[choice - 1];
+    let mut export_options = ExportOptions::default();
+    if exporter.uses_set_size() {
+        export_options.set_size = get_set_size();
+    }
+    if exporter.uses_choices() {
+        export_options.choices = get_choices();
+    }
+    // questions can be turned round here as well as on import, the same way
+    let mut options = ImportOptions {
+        direction: get_direction(),
+        ..ImportOptions::default()
+    };
+    if options.direction != Direction::LatinToEnglish {
+        options.marking = get_marking();
+    }
+    if options.direction != Direction::EnglishToLatin {
+        options.macrons = confirm("Show macrons in the Latin, e.g. portāre? (y/n) ");
+    }
+    let exported = apply_direction(questions.to_vec(), &options);
+    let questions = &exported[..];
+    let config = config::get();
     let stage_number = get_input_with_initial(
         "Enter a test no. or modify folder path: ",
-        "top/Vocabulary/A_",
-    );
-    let ex_name = get_input_with_initial(
-        "Enter a basename for questions: ",
-        &stage_number.replace("top/Vocabulary/", ""),
+        &config.category_prefix,
     );
+    let mut tests = questions
+        .iter()
+        .filter_map(|q| q.test)
+        .collect::<Vec<u32>>();
+    tests.sort_unstable();
+    tests.dedup();
+    let per_test = tests.len() > 1
+        && confirm(&format!(
+            "Write one category per test ({} tests)? (y/n) ",
+            tests.len()
+        ));
+    let ex_name = match per_test {
+        true => format!(
+            "{}_{}-{}",
+            basename_for(&stage_number),
+            tests[0],
+            tests[tests.len() - 1]
+        ),
+        false => get_input_with_initial(
+            "Enter a basename for questions: ",
+            stage_number.rsplit('/').next().unwrap_or(""),
+        ),
+    };
+    // the file is named after the basename, in the folder of the file in the settings
+    let default_file = match exporter.extension() {
+        "gift" => &config.gift_file,
+        _ => &config.xml_file,
+    };
+    let default_file = Path::new(default_file);
+    let proposed = match ex_name.trim() {
+        "" => default_file.with_extension(exporter.extension()),
+        name => default_file
+            .with_file_name(name)
+            .with_extension(exporter.extension()),
+    };
+    let output = loop {
+        let output = get_input_with_initial("Write to: ", &proposed.display().to_string());
+        let output = PathBuf::from(output.trim());
+        if output.file_name().is_some() {
+            break output;
+        }
+        show_message("Enter a file to write to.");
+    };
+    let output = output.as_path();
+    // written next to the real file first, so it can be compared with what is there
+    let mut new_name = output.file_name().unwrap_or_default().to_os_string();
+    new_name.push(".new");
+    let new_file = output.with_file_name(new_name);
+    let categories = match per_test {
+        true => Category::by_test(&stage_number, questions),
+        false => vec![Category::single(&stage_number, &ex_name, questions)],
+    };
+    let notes = match exporter.write(&new_file, &categories, &export_options) {
+        Ok(notes) => notes,
+        Err(e) => {
+            let _ = fs::remove_file(&new_file);
+            show_message(&format!("Unable to write {}: {}", output.display(), e));
+            return;
+        }
+    };
+    if output.exists() && !confirm_overwrite(output, &new_file) {
+        let _ = fs::remove_file(&new_file);
+        show_message(&format!("{} was left as it was.", output.display()));
+        return;
+    }
+    // display message to user
+    match fs::rename(&new_file, output) {
+        Ok(()) => show_message(&format!(
+            "{} written! {}",
+            output.display(),
+            notes.join(" ")
+        )),
+        Err(e) => {
+            let _ = fs::remove_file(&new_file);
+            show_message(&format!("Unable to write {}: {}", output.display(), e));
+        }
+    }
+}
 
-    // set up Writer
-    let f = File::create("./upload.xml").expect("Unable to create file");
-    let mut writer = BufWriter::new(f);
-
-    // make initial write
-    writer
-        .write(xml_start!(stage_number))
-        .expect("Unable to write xml start.");
-
-    let mut question_number: u32 = 1000;
-    let mut question_name: String;
-    let mut question_word: &str;
-    let mut question_code = String::new();
-    //Now loop over question data
-    for question in questions.iter() {
-        question_number += 1;
-        question_name = format!("{}_q_{}", ex_name, question_number);
-        question_word = question.latin.as_str();
-        // question_code = moodle_shortanswer(&question.answers[num]);
-        question_code.clear();
-        question_code = format!(
-            "{} {}",
-            question_code,
-            moodle_shortanswer(&question.answers)
-        );
-        writer
-            .write(xml_question!(
-                question_number,
-                question_name,
-                question_word,
-                question_code
+// Show what writing the new file over the old one would change, and ask
+fn confirm_overwrite(output: &Path, new_file: &Path) -> bool {
+    let (old, new) = match (fs::read_to_string(output), fs::read_to_string(new_file)) {
+        (Ok(old), Ok(new)) => (old, new),
+        // not one of ours, or unreadable: there is nothing to compare
+        _ => {
+            return confirm(&format!(
+                "{} already exists. Overwrite it? (y/n) ",
+                output.display()
             ))
-            .expect("Unable to write xml question.");
+        }
+    };
+    let summary = compare::compare(&old, &new);
+    MAIN_WIN.with(|main_win| {
+        let max_lines: i32 = getmaxy(*main_win);
+        overwrite_win(
+            *main_win,
+            &format!(
+                "{} already exists. Compared with it, this export has:\n\
+                 {} questions unchanged, {} changed, {} added and {} removed.",
+                output.display(),
+                summary.unchanged,
+                summary.changed.len(),
+                summary.added.len(),
+                summary.removed.len()
+            ),
+        );
+        let lines = summary
+            .changed
+            .iter()
+            .map(|l| format!("~ {}", l))
+            .chain(summary.added.iter().map(|l| format!("+ {}", l)))
+            .chain(summary.removed.iter().map(|l| format!("- {}", l)));
+        for (line_count, line) in (4..).zip(lines) {
+            if line_count >= max_lines - 1 {
+                mvwaddstr(*main_win, max_lines - 2, 1, "...");
+                break;
+            }
+            mvwaddstr(*main_win, line_count, 1, &line);
+        }
+        wrefresh(*main_win);
+    });
+    let prompt = match summary.is_empty() {
+        true => "Nothing has changed. Overwrite anyway? (y/n) ",
+        false => "Overwrite it? (y/n) ",
+    };
+    confirm(prompt)
+}
+
+// How many words go in each matching question
+fn get_set_size() -> usize {
+    loop {
+        match get_input_with_initial(
+            "Words in each set: ",
+            &matching::DEFAULT_SET_SIZE.to_string(),
+        )
+        .trim()
+        .parse::<usize>()
+        {
+            Ok(size) if size >= matching::MIN_SET_SIZE => return size,
+            _ => show_message(&format!(
+                "Enter a number of words, at least {}.",
+                matching::MIN_SET_SIZE
+            )),
+        }
     }
-    writer
-        .write(xml_end.as_bytes())
-        .expect("Unable to write xml end.");
-    writer.flush().expect("Unable to write data.");
-    // display message to user
+}
+
+// Change the settings for this run, and save them for every project or just this one
+fn settings() {
+    let mut config = config::get();
+    loop {
+        let fields: Vec<(&str, String)> = vec![
+            ("Category prefix", config.category_prefix.clone()),
+            ("Start number", config.start_number.to_string()),
+            ("Font", config.font.clone()),
+            ("Moodle XML file", config.xml_file.clone()),
+            ("GIFT file", config.gift_file.clone()),
+            ("Right answer feedback", config.correct_feedback.clone()),
+            ("Close answer mark", config.close_mark.to_string()),
+            ("Close answer feedback", config.close_feedback.clone()),
+            ("Wrong part mark", config.wrong_form_mark.to_string()),
+            ("Wrong part feedback", config.wrong_form_feedback.clone()),
+        ];
+        MAIN_WIN.with(|main_win| {
+            overwrite_win(*main_win, "Settings:");
+            for (num, (name, value)) in fields.iter().enumerate() {
+                mvwaddstr(
+                    *main_win,
+                    3 + num as i32,
+                    1,
+                    &format!("{:2}. {:22}| {}", num + 1, name, value),
+                );
+            }
+            wrefresh(*main_win);
+        });
+        KEYS_WIN.with(|keys_win| {
+            overwrite_win(
+                *keys_win,
+                "e: edit    u: save for user    p: save for project    b: back",
+            );
+        });
+        match char::from_u32(getch() as u32).unwrap() {
+            'e' => {
+                let choice = get_num_input(0, fields.len() - 1);
+                let value =
+                    get_input_with_initial(&format!("{}: ", fields[choice].0), &fields[choice].1);
+                let mark = || value.trim().parse::<u8>().ok().filter(|m| *m <= 100);
+                match choice {
+                    0 => config.category_prefix = value,
+                    1 => match value.trim().parse::<u32>() {
+                        Ok(num) => config.start_number = num,
+                        Err(_) => show_message("The start number must be a whole number."),
+                    },
+                    2 => config.font = value,
+                    3 => config.xml_file = value,
+                    4 => config.gift_file = value,
+                    5 => config.correct_feedback = value,
+                    6 => match mark() {
+                        Some(mark) => config.close_mark = mark,
+                        None => show_message("A mark must be from 0 to 100."),
+                    },
+                    7 => config.close_feedback = value,
+                    8 => match mark() {
+                        Some(mark) => config.wrong_form_mark = mark,
+                        None => show_message("A mark must be from 0 to 100."),
+                    },
+                    _ => config.wrong_form_feedback = value,
+                }
+                config::set(config.clone());
+            }
+            'u' => match config::user_file() {
+                Some(path) => match config::save(&path, &config) {
+                    Ok(()) => show_message(&format!("{} written!", path.display())),
+                    Err(e) => show_message(&format!("Unable to write {}: {}", path.display(), e)),
+                },
+                None => show_message("Unable to find your home folder."),
+            },
+            'p' => match config::save(Path::new(config::PROJECT_FILE), &config) {
+                Ok(()) => show_message(&format!("{} written!", config::PROJECT_FILE)),
+                Err(e) => show_message(&format!("Unable to write {}: {}", config::PROJECT_FILE, e)),
+            },
+            'b' => break,
+            _ => continue,
+        }
+    }
+}
+
+fn lookup_problems() {
+    loop {
+        let problems = verb_cache::problems();
+        MAIN_WIN.with(|main_win| {
+            let max_lines: i32 = getmaxy(*main_win);
+            overwrite_win(
+                *main_win,
+                &format!(
+                    "Verb lookups that need attention: {}\n\n\
+                     Add a line for each to {} and re-import.\n\
+                     Columns: English,Present,Past Simple,Past Participle",
+                    problems.len(),
+                    verb_cache::OVERRIDES_FILE
+                ),
+            );
+            for (num, problem) in problems.iter().enumerate() {
+                let line = 6 + num as i32;
+                if line >= max_lines - 1 {
+                    break;
+                }
+                mvwaddstr(
+                    *main_win,
+                    line,
+                    1,
+                    &format!("{:25}| {}", problem.english, problem.reason),
+                );
+            }
+            wrefresh(*main_win);
+        });
+        KEYS_WIN.with(|keys_win| {
+            overwrite_win(*keys_win, "r: reload overrides    c: clear list    b: back");
+        });
+        match char::from_u32(getch() as u32).unwrap() {
+            'r' => match verb_cache::reload_overrides() {
+                Ok(()) => show_message("Overrides reloaded!"),
+                Err(e) => show_message(&format!("Unable to read overrides: {}", e)),
+            },
+            'c' => verb_cache::clear_problems(),
+            'b' => break,
+            _ => continue,
+        }
+    }
+}
+
+fn save_session(questions: &[Question]) {
+    let current = SESSION_FILE.with(|f| f.borrow().clone());
+    let initial = match &current {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => session::DEFAULT_SESSION.to_string(),
+    };
+    let path = PathBuf::from(get_input_with_initial("Save session as: ", &initial));
+    let message = match session::save(&path, questions) {
+        Ok(()) => {
+            session::discard_autosave(current.as_deref());
+            SESSION_FILE.with(|f| *f.borrow_mut() = Some(path.clone()));
+            format!("{} written!", path.display())
+        }
+        Err(e) => format!("Unable to save session: {}", e),
+    };
+    show_message(&message);
+}
+
+fn open_session(questions: &mut Vec<Question>) {
+    if !questions.is_empty() && !confirm("Discard the current questions? (y/n) ") {
+        return;
+    }
+    let path = get_file();
+    let mut source = path.clone();
+    if session::has_newer_autosave(&path)
+        && confirm("There are unsaved changes from last time. Recover them? (y/n) ")
+    {
+        source = session::autosave_path(Some(&path));
+    }
+    match session::load(&source) {
+        Ok(loaded) => {
+            *questions = loaded;
+            SESSION_FILE.with(|f| *f.borrow_mut() = Some(path.clone()));
+            show_message(&format!("{} opened!", path.display()));
+        }
+        Err(e) => show_message(&format!("Unable to open session: {}", e)),
+    }
+}
+
+// Until a session is opened or saved, changes are autosaved next to the
+// default session file, so the last run's unsaved work is offered back
+// before the first change overwrites it
+fn recover_autosave(questions: &mut Vec<Question>) {
+    let default = Path::new(session::DEFAULT_SESSION);
+    if !session::has_newer_autosave(default)
+        || !confirm("There are unsaved changes from last time. Recover them? (y/n) ")
+    {
+        return;
+    }
+    match session::load(&session::autosave_path(None)) {
+        Ok(loaded) => *questions = loaded,
+        Err(e) => show_message(&format!("Unable to recover: {}", e)),
+    }
+}
+
+// Called after every change so that a crash or a careless 'q' loses nothing
+fn autosave(questions: &[Question]) {
+    let current = SESSION_FILE.with(|f| f.borrow().clone());
+    if let Err(e) = session::autosave(current.as_deref(), questions) {
+        show_message(&format!("Autosave failed: {}", e));
+    }
+}
+
+fn show_message(message: &str) {
     INPUT_WIN.with(|input_win| {
         wclear(*input_win);
-        mvwaddstr(*input_win, 0, 0, "upload.xml written!");
+        mvwaddstr(*input_win, 0, 0, message);
         wrefresh(*input_win);
     });
     getch();
 }
 
-fn moodle_shortanswer(answers: &Vec<AnswerOption>) -> String {
-    let mut question_string = String::new();
-    for answer in answers {
-        question_string = format!(
-            "{}~%{}%{}#{}",
-            question_string, answer.mark, answer.answer, answer.feedback
-        );
-    }
-    format!("{{1:SHORTANSWER:{}}}", question_string)
-}
-
 fn import(questions: &mut Vec<Question>) {
     let file: PathBuf = get_file();
-    let mut rdr = Reader::from_path(file).unwrap();
+    // a question bank exported from Moodle rather than a vocab list
+    if file.extension().map(|e| e == "xml").unwrap_or(false) {
+        match moodle::read_xml(&file) {
+            Ok(mut import) => {
+                questions.append(&mut import.questions);
+                if !import.skipped.is_empty() {
+                    import_report(
+                        &import.skipped,
+                        "questions could not be read and were skipped.",
+                    );
+                }
+            }
+            Err(e) => show_message(&format!("Unable to import: {}", e)),
+        }
+        return;
+    }
+    let mut options = ImportOptions {
+        tests: get_test_filter(),
+        noun_parts: confirm("Split nouns into meaning, genitive and gender questions? (y/n) "),
+        comparison: confirm("Add comparative and superlative questions for adjectives? (y/n) "),
+        direction: get_direction(),
+        marking: Marking::default(),
+        macrons: true,
+        choices: None,
+        verbs: get_verb_strategy(),
+        wrong_forms: WrongForms::default(),
+    };
+    if options.direction != Direction::EnglishToLatin {
+        options.macrons = confirm("Show macrons in the Latin, e.g. portāre? (y/n) ");
+        if confirm("Multiple choice instead of typed English? (y/n) ") {
+            options.choices = Some(get_choices());
+        }
+    }
+    if options.direction != Direction::LatinToEnglish {
+        options.marking = get_marking();
+    }
     INPUT_WIN.with(|input_win| {
         wclear(*input_win);
-        mvwaddstr(*input_win, 0, 0, "Loading questions:");
+        mvwaddstr(
+            *input_win,
+            0,
+            0,
+            &format!("Loading questions ({}):", options.tests),
+        );
         wrefresh(*input_win);
         wmove(*input_win, 1, 0);
     });
-    let (tx, rx) = mpsc::channel();
-    for result in rdr.deserialize() {
-        let record: Record = result.unwrap();
-        match &record.part_of_speech {
-            pos if pos.contains("verb") => {
-                let tx1 = mpsc::Sender::clone(&tx);
-                thread::spawn(move || {
-                    let verb_questions: Vec<Question> = build_verb(record.latin, record.english);
-                    tx1.send(verb_questions).unwrap();
-                });
+    match import_file(&file, &options, &progress_bar) {
+        Ok(mut import) => {
+            questions.append(&mut import.questions);
+            if !import.skipped.is_empty() {
+                import_report(
+                    &import.skipped,
+                    "rows were skipped. Fix them in the spreadsheet and import again.",
+                );
             }
-            _ => {
-                build_non_verb(questions, record.latin, record.english);
-                progress_bar();
+        }
+        Err(e) => show_message(&format!("Unable to import: {}", e)),
+    }
+}
+
+fn import_report(skipped: &[ImportError], heading: &str) {
+    MAIN_WIN.with(|main_win| {
+        let max_lines: i32 = getmaxy(*main_win);
+        overwrite_win(*main_win, &format!("{} {}", skipped.len(), heading));
+        for (line_count, error) in (3..).zip(skipped) {
+            if line_count >= max_lines - 1 {
+                mvwaddstr(*main_win, max_lines - 2, 1, "...");
+                break;
             }
+            let location = match (&error.line, &error.field) {
+                (Some(line), Some(field)) => format!("line {}, {}", line, field),
+                (Some(line), None) => format!("line {}", line),
+                _ => String::new(),
+            };
+            mvwaddstr(
+                *main_win,
+                line_count,
+                1,
+                &format!("{:25}| {}", location, error.reason),
+            );
+        }
+        wrefresh(*main_win);
+    });
+    prompt();
+}
+
+fn get_direction() -> Direction {
+    MAIN_WIN.with(|main_win| {
+        overwrite_win(
+            *main_win,
+            "Which way should the questions go?\n\
+             1. Latin to English\n\
+             2. English to Latin\n\
+             3. Both",
+        )
+    });
+    match get_input("Which direction? ").trim() {
+        "2" => Direction::EnglishToLatin,
+        "3" => Direction::Both,
+        _ => Direction::LatinToEnglish,
+    }
+}
+
+// How to mark the Latin typed into English -> Latin questions
+fn get_marking() -> Marking {
+    let mut marking = Marking::default();
+    show_message("Next: the mark for Latin typed with its macrons.");
+    marking.macron_mark = get_mark();
+    show_message("Next: the mark for Latin typed without its macrons.");
+    marking.plain_mark = get_mark();
+    show_message("Next: the mark for the right stem with the wrong ending.");
+    marking.stem_mark = get_mark();
+    marking.ij = confirm("Accept j for consonantal i, e.g. jam for iam? (y/n) ");
+    marking.uv = confirm("Accept v for consonantal u, e.g. vir for uir? (y/n) ");
+    marking
+}
+
+fn get_verb_strategy() -> VerbStrategy {
+    match confirm("One question per verb with a gap for each principal part? (y/n) ") {
+        true => VerbStrategy::PrincipalParts,
+        false => VerbStrategy::Separate,
+    }
+}
+
+// How many options each multiple choice menu has, and the seed for their order
+fn get_choices() -> Choices {
+    let mut choices = Choices::default();
+    loop {
+        match get_input_with_initial("Options in each menu: ", &choices.count.to_string())
+            .trim()
+            .parse::<usize>()
+        {
+            Ok(count) if count >= 2 => {
+                choices.count = count;
+                break;
+            }
+            _ => show_message("Enter a number of options, at least 2."),
+        }
+    }
+    loop {
+        match get_input_with_initial("Seed for shuffling: ", &choices.seed.to_string())
+            .trim()
+            .parse::<u64>()
+        {
+            Ok(seed) => {
+                choices.seed = seed;
+                break;
+            }
+            _ => show_message("Enter a whole number."),
+        }
+    }
+    choices
+}
+
+fn get_test_filter() -> TestFilter {
+    MAIN_WIN.with(|main_win| {
+        overwrite_win(
+            *main_win,
+            "Which tests should be imported?\n\n\
+             Use the numbers from the file's Test column:\n\
+             - one test, e.g. 20\n\
+             - a range, e.g. 18-20 for a cumulative test\n\
+             - a list, e.g. 18,19,22\n\n\
+             Leave blank to import every word.",
+        )
+    });
+    loop {
+        match TestFilter::parse(&get_input("Tests to import: ")) {
+            Ok(filter) => return filter,
+            Err(e) => show_message(&e),
         }
     }
-    drop(tx);
-    for verb_questions in &rx {
-        for verb_question in verb_questions {
-            questions.push(verb_question);
-            progress_bar();
+}
+
+fn add_question(questions: &mut Vec<Question>) {
+    MAIN_WIN.with(|main_win| {
+        overwrite_win(
+            *main_win,
+            "Add a question:\n\n\
+             Enter the Latin headword as it appears in a vocab list,\n\
+             e.g. \"porto, portare, portavi, portatus\" or \"annus\".\n\n\
+             Then enter the part of speech, e.g. verb, noun 2 m, adj 212,\n\
+             and the English meanings separated by commas.",
+        );
+    });
+    let latin = get_input("Latin headword: ");
+    if latin.trim().is_empty() {
+        return;
+    }
+    let part_of_speech: PartOfSpeech = loop {
+        match get_input("Part of speech: ").parse() {
+            Ok(pos) => break pos,
+            Err(e) => show_message(&e),
+        }
+    };
+    let english = get_input("English meaning(s): ");
+    let mut options = ImportOptions::default();
+    match part_of_speech {
+        PartOfSpeech::Noun { .. } => {
+            options.noun_parts =
+                confirm("Split into meaning, genitive and gender questions? (y/n) ");
+        }
+        PartOfSpeech::Adjective { .. } => {
+            options.comparison = confirm("Add comparative and superlative questions? (y/n) ");
         }
+        PartOfSpeech::Verb { .. } => options.verbs = get_verb_strategy(),
+        _ => (),
+    }
+    let record = Record {
+        latin: spelling::normalize(latin.trim()),
+        part_of_speech: part_of_speech.to_string(),
+        english: spelling::normalize(&english),
+        test: None,
+    };
+    options.direction = get_direction();
+    if options.direction != Direction::LatinToEnglish {
+        options.marking = get_marking();
+    }
+    let new_questions = build_word(record, &part_of_speech, &options);
+    let _ = verb_cache::save();
+    let mut new_questions = apply_direction(new_questions, &options);
+    show_questions(&new_questions);
+    if confirm("Add these questions? (y/n) ") {
+        questions.append(&mut new_questions);
     }
 }
 
+fn show_questions(questions: &[Question]) {
+    MAIN_WIN.with(|main_win| {
+        let max_lines: i32 = getmaxy(*main_win);
+        let mut line_count: i32 = 1;
+        wclear(*main_win);
+        box_(*main_win, 0, 0);
+        for (num, question) in questions.iter().enumerate() {
+            if line_count >= max_lines - 1 {
+                break;
+            }
+            mvwaddstr(
+                *main_win,
+                line_count,
+                1,
+                &format!("{}: {}", num + 1, question.latin),
+            );
+            line_count += 1;
+            for q_str in question_lines(question) {
+                if line_count >= max_lines - 1 {
+                    break;
+                }
+                mvwaddstr(*main_win, line_count, 1, &q_str);
+                line_count += 1;
+            }
+        }
+        wrefresh(*main_win);
+    });
+}
+
+// The answers of every gap in a question, one line each, for the pager
+fn question_lines(question: &Question) -> Vec<String> {
+    let answer_line = |answer: &AnswerOption| {
+        format!(
+            "{:5} | {:30}| {:28}",
+            answer.mark, answer.answer, answer.feedback
+        )
+    };
+    let mut lines: Vec<String> = question.answers.iter().map(answer_line).collect();
+    for gap in &question.extra_gaps {
+        lines.push(format!("    + {}", gap.prompt));
+        lines.extend(gap.answers.iter().map(answer_line));
+    }
+    lines
+}
+
+fn confirm(prompt: &str) -> bool {
+    matches!(get_input(prompt).trim(), "y" | "Y" | "yes")
+}
+
 fn progress_bar() {
     INPUT_WIN.with(|input_win| {
         waddstr(*input_win, ".");
@@ -285,151 +978,6 @@ fn list_files() -> Result<Vec<PathBuf>, Box<dyn Error>> {
     Ok(list)
 }
 
-fn clean_text(s: &str) -> String {
-    let re1 = Regex::new(r"\(.*\)").unwrap();
-    let re2 = Regex::new(r"\s*GEN").unwrap();
-    let re3 = Regex::new(r"\s*ACC").unwrap();
-    let re4 = Regex::new(r"\s*DAT").unwrap();
-    let re5 = Regex::new(r";").unwrap();
-    let re6 = Regex::new(r"/").unwrap();
-    let s = re1.replace_all(&s, "");
-    let s = re2.replace_all(&s, "");
-    let s = re3.replace_all(&s, "");
-    let s = re4.replace_all(&s, "");
-    let s = re5.replace_all(&s, ",");
-    let s = re6.replace_all(&s, ",");
-    format!("{}", s)
-}
-
-fn build_non_verb(questions: &mut Vec<Question>, latin: String, english: String) {
-    let mut answer_options: Vec<AnswerOption> = Vec::new();
-    let english = clean_text(&english);
-    for answer in english.split(",").collect::<Vec<&str>>() {
-        let answer_option = AnswerOption {
-            mark: 100,
-            answer: answer.trim().to_string(),
-            feedback: "Well done!".to_string(),
-        };
-        answer_options.push(answer_option);
-    }
-    let question = Question {
-        latin: latin,
-        answers: answer_options,
-    };
-    questions.push(question);
-}
-
-fn build_verb(latin: String, english: String) -> Vec<Question> {
-    let mut questions: Vec<Question> = Vec::new();
-    let english = english.replace("I ", "");
-    let english = clean_text(&english);
-    let answers = english.split(",").collect::<Vec<&str>>();
-    let mut verb_collection: Vec<Verb> = Vec::new();
-    for answer in &answers {
-        let verb = lookup(answer.trim());
-        verb_collection.push(verb);
-    }
-    // println!("Answers: {:?}", answers);
-    let latin_parts = latin.split(",").collect::<Vec<&str>>();
-    // println!("Parts: {:?}", latin_parts);
-    //present tense
-    let mut answer_options: Vec<AnswerOption> = Vec::new();
-    for verb in &verb_collection {
-        // println!("Present: {}", answer.trim());
-        let answer_option = AnswerOption {
-            mark: 100,
-            answer: format!("I {}", verb.present),
-            feedback: "Well done!".to_string(),
-        };
-        answer_options.push(answer_option);
-        // prepare partially correct answer with wildcards
-        let answer_option_close = AnswerOption {
-            mark: 50,
-            answer: verb.asterisked.clone(),
-            feedback: "Close! What part of the verb is this?".to_string(),
-        };
-        answer_options.push(answer_option_close);
-    }
-    let present = Question {
-        latin: latin_parts[0].trim().to_string(),
-        answers: answer_options,
-    };
-    questions.push(present);
-    // infinitive
-    if latin_parts.len() > 1 {
-        let mut answer_options: Vec<AnswerOption> = Vec::new();
-        for verb in &verb_collection {
-            let answer_option = AnswerOption {
-                mark: 100,
-                answer: format!("to {}", verb.present),
-                feedback: "Well done!".to_string(),
-            };
-            answer_options.push(answer_option);
-            // prepare partially correct answer with wildcards
-            let answer_option_close = AnswerOption {
-                mark: 50,
-                answer: verb.asterisked.clone(),
-                feedback: "Close! What part of the verb is this?".to_string(),
-            };
-            answer_options.push(answer_option_close);
-        }
-        let infinitive = Question {
-            latin: latin_parts[1].trim().to_string(),
-            answers: answer_options,
-        };
-        questions.push(infinitive);
-    }
-    // perfect tense
-    if latin_parts.len() > 2 {
-        let mut answer_options: Vec<AnswerOption> = Vec::new();
-        for verb in &verb_collection {
-            let answer_option = AnswerOption {
-                mark: 100,
-                answer: format!("I {}", verb.past_simple),
-                feedback: "Well done!".to_string(),
-            };
-            answer_options.push(answer_option);
-            // prepare partially correct answer with wildcards
-            let answer_option_close = AnswerOption {
-                mark: 50,
-                answer: verb.asterisked.clone(),
-                feedback: "Close! What part of the verb is this?".to_string(),
-            };
-            answer_options.push(answer_option_close);
-        }
-        let perfect = Question {
-            latin: latin_parts[2].trim().to_string(),
-            answers: answer_options,
-        };
-        questions.push(perfect);
-    }
-    // passive participle
-    if latin_parts.len() > 3 {
-        let mut answer_options: Vec<AnswerOption> = Vec::new();
-        for verb in &verb_collection {
-            let answer_option = AnswerOption {
-                mark: 100,
-                answer: format!("having been {}", verb.past_part),
-                feedback: "Well done!".to_string(),
-            };
-            answer_options.push(answer_option);
-            // prepare partially correct answer with wildcards
-            let answer_option_close = AnswerOption {
-                mark: 50,
-                answer: verb.asterisked.clone(),
-                feedback: "Close! What part of the verb is this?".to_string(),
-            };
-            answer_options.push(answer_option_close);
-        }
-        let passive_pt = Question {
-            latin: latin_parts[3].trim().to_string(),
-            answers: answer_options,
-        };
-        questions.push(passive_pt);
-    }
-    questions
-}
-
 fn pager(questions: &mut Vec<Question>) {
     // update keys_win
     fn pager_keys() {
@@ -459,7 +1007,7 @@ fn pager(questions: &mut Vec<Question>) {
                 next_cached_q = current_q;
             }
             if current_q < max_qs {
-                lines_required = questions[current_q].answers.len();
+                lines_required = question_lines(&questions[current_q]).len();
             }
             if current_q < max_qs && lines_required < (max_lines - (line_count + 3)) as usize {
                 mvwaddstr(
@@ -471,12 +1019,8 @@ fn pager(questions: &mut Vec<Question>) {
                 // mvwin(*main_win, line_count, 1);
                 // print!("{}: {}", current_q + 1, questions[current_q].latin);
                 line_count += 1;
-                for answer in &questions[current_q].answers {
-                    let q_str: &str = &format!(
-                        "{:5} | {:30}| {:28}",
-                        answer.mark, answer.answer, answer.feedback
-                    );
-                    mvwaddstr(*main_win, line_count, 1, q_str);
+                for q_str in question_lines(&questions[current_q]) {
+                    mvwaddstr(*main_win, line_count, 1, &q_str);
                     line_count += 1;
                 }
                 current_q += 1;
@@ -513,6 +1057,7 @@ fn pager(questions: &mut Vec<Question>) {
                     }
                     'e' => {
                         edit(questions);
+                        autosave(questions);
                         wclear(*main_win);
                         box_(*main_win, 0, 0);
                         wrefresh(*main_win);
@@ -522,6 +1067,7 @@ fn pager(questions: &mut Vec<Question>) {
                     }
                     'c' => {
                         duplicate(questions);
+                        autosave(questions);
                         wclear(*main_win);
                         box_(*main_win, 0, 0);
                         wrefresh(*main_win);
@@ -539,6 +1085,7 @@ fn pager(questions: &mut Vec<Question>) {
                     }
                     'd' => {
                         remove_question(questions);
+                        autosave(questions);
                         wclear(*main_win);
                         box_(*main_win, 0, 0);
                         wrefresh(*main_win);
@@ -581,10 +1128,10 @@ fn duplicate(questions: &mut Vec<Question>) {
     questions.insert(choice, dup);
 }
 
-fn edit(questions: &mut Vec<Question>) {
+fn edit(questions: &mut [Question]) {
     let choice = get_num_input(0, questions.len());
-    let question = &mut questions[choice];
     loop {
+        let question = &mut questions[choice];
         MAIN_WIN.with(|main_win| {
             let mut line_count: i32 = 1;
             overwrite_win(*main_win, &format!("1: {}", question.latin));
@@ -618,6 +1165,7 @@ fn edit(questions: &mut Vec<Question>) {
             'b' => break,
             _ => continue,
         }
+        autosave(questions);
     }
 }
 
@@ -706,46 +1254,54 @@ fn add_answer(question: &mut Question) {
     // Get feedback
     let feedback = get_feedback();
     let answeroption = AnswerOption {
-        mark: mark,
-        answer: answer,
-        feedback: feedback,
+        mark,
+        answer,
+        feedback,
     };
     question.answers.push(answeroption);
 }
 
 fn get_mark() -> u8 {
+    let close_mark = config::get().close_mark;
     MAIN_WIN.with(|main_win| {
         overwrite_win(
             *main_win,
-            "Choose a mark.\n\
-             1. 100%\n\
-             2. 50%\n\
-             3. 0%",
+            &format!(
+                "Choose a mark.\n\
+                 1. 100%\n\
+                 2. {}%\n\
+                 3. 0%",
+                close_mark
+            ),
         )
     });
     match get_input("Which mark? ").as_str() {
-        "1" => return 100,
-        "2" => return 50,
-        _ => return 0,
-    };
+        "1" => 100,
+        "2" => close_mark,
+        _ => 0,
+    }
 }
 
 fn get_feedback() -> String {
+    let config = config::get();
     MAIN_WIN.with(|main_win| {
         overwrite_win(
             *main_win,
-            "Choose feedback.\n\
-             1. Well done!\n\
-             2. Close! What tense is this?\n\
-             3. Input something else.",
+            &format!(
+                "Choose feedback.\n\
+                 1. {}\n\
+                 2. {}\n\
+                 3. Input something else.",
+                config.correct_feedback, config.close_feedback
+            ),
         )
     });
     match get_input("Which feedback? ").as_str() {
-        "1" => return "Well done!".to_string(),
-        "2" => return "Close! What tense is this?".to_string(),
-        "3" => return get_input("Enter your feedback: "),
-        _ => return "XXXX".to_string(),
-    };
+        "1" => config.correct_feedback,
+        "2" => config.close_feedback,
+        "3" => get_input("Enter your feedback: "),
+        _ => "XXXX".to_string(),
+    }
 }
 
 fn delete_answer(question: &mut Question) {
@@ -0,0 +1,262 @@
+use crate::config;
+use crate::escape;
+use crate::moodle;
+use crate::{Category, GapType, Question};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Moodle matching questions, each with a set of Latin words to match to
+// their English. The English for a word is its question's first full-mark
+// answer. Words whose English could be mistaken for each other, like bonus
+// and bona (good) or et and atque (and), are never put in the same set.
+
+pub const DEFAULT_SET_SIZE: usize = 5;
+
+// Moodle needs at least three answers to offer in a matching question
+pub const MIN_SET_SIZE: usize = 3;
+
+// One Latin word and the English it is matched with
+#[derive(Debug, Clone)]
+struct Pair {
+    latin: String,
+    english: String,
+    // every full-mark answer, to spot words that share a meaning
+    meanings: Vec<String>,
+}
+
+impl Pair {
+    // Only typed questions: "masculine" is not a meaning of desperatio
+    fn from_question(question: &Question) -> Option<Pair> {
+        if question.gap_type != GapType::ShortAnswer || question.has_typed_extra_gaps() {
+            return None;
+        }
+        let meanings = question
+            .answers
+            .iter()
+            .filter(|a| a.mark == 100)
+            .map(|a| a.answer.trim().to_lowercase())
+            .collect::<Vec<String>>();
+        let english = question.answers.iter().find(|a| a.mark == 100)?;
+        Some(Pair {
+            latin: question.latin.trim().to_string(),
+            english: english.answer.trim().to_string(),
+            meanings,
+        })
+    }
+
+    fn clashes_with(&self, other: &Pair) -> bool {
+        self.latin == other.latin
+            || other.meanings.contains(&self.english.to_lowercase())
+            || self.meanings.contains(&other.english.to_lowercase())
+    }
+}
+
+// Words that could not be put in any set
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LeftOut {
+    // clashed with a word in every set
+    pub clashed: usize,
+    // there were too few words left to make up a set
+    pub too_few: usize,
+}
+
+fn fits(set: &[Pair], pair: &Pair) -> bool {
+    !set.iter().any(|p| p.clashes_with(pair))
+}
+
+// Put each word into the first set that has room and nothing it clashes
+// with. No set is bigger than `set_size`. Words left in a set too small for
+// Moodle go into other sets with room, or take spare words from bigger sets
+// to make up a set of their own; any that still can't be placed are left out.
+fn make_sets(questions: &[&Question], set_size: usize) -> (Vec<Vec<Pair>>, LeftOut) {
+    let pairs = questions.iter().filter_map(|q| Pair::from_question(q));
+    let (mut sets, small) = fill_sets(pairs, set_size);
+    let mut rest: Vec<Pair> = Vec::new();
+    for pair in small.into_iter().flatten() {
+        match sets
+            .iter_mut()
+            .find(|set| set.len() < set_size && fits(set, &pair))
+        {
+            Some(set) => set.push(pair),
+            None => rest.push(pair),
+        }
+    }
+    let (_, groups) = fill_sets(rest.into_iter(), set_size);
+    let mut left_out = LeftOut::default();
+    for mut group in groups {
+        while group.len() < MIN_SET_SIZE {
+            let spare = sets
+                .iter_mut()
+                .filter(|set| set.len() > MIN_SET_SIZE)
+                .find_map(|set| {
+                    let position = set.iter().position(|p| fits(&group, p))?;
+                    Some(set.remove(position))
+                });
+            match spare {
+                Some(pair) => group.push(pair),
+                None => break,
+            }
+        }
+        if group.len() >= MIN_SET_SIZE {
+            sets.push(group);
+            continue;
+        }
+        for pair in group {
+            match sets
+                .iter()
+                .position(|set| set.len() < set_size && fits(set, &pair))
+            {
+                Some(position) => sets[position].push(pair),
+                None if sets.iter().any(|set| fits(set, &pair)) => left_out.too_few += 1,
+                None => left_out.clashed += 1,
+            }
+        }
+    }
+    (sets, left_out)
+}
+
+// Add each pair to the first set with room and no clash, starting new sets
+// as needed. Returns the sets big enough for Moodle and those that aren't.
+fn fill_sets(
+    pairs: impl Iterator<Item = Pair>,
+    set_size: usize,
+) -> (Vec<Vec<Pair>>, Vec<Vec<Pair>>) {
+    let mut sets: Vec<Vec<Pair>> = Vec::new();
+    for pair in pairs {
+        match sets
+            .iter_mut()
+            .find(|set| set.len() < set_size && fits(set, &pair))
+        {
+            Some(set) => set.push(pair),
+            None => sets.push(vec![pair]),
+        }
+    }
+    sets.into_iter().partition(|set| set.len() >= MIN_SET_SIZE)
+}
+
+// fields are question_number, question_name, subquestions
+macro_rules! xml_matching {
+    ($q_num:expr, $q_name:expr, $subquestions:expr) => {
+        format!(
+            "<!-- question: {}  -->\n\
+             <question type=\"matching\">\n\
+             <name>\n\
+             <text>{}</text>\n\
+             </name>\n\
+             <questiontext format=\"html\">\n\
+             <text><![CDATA[<p>Match each Latin word to its meaning.</p>]]></text>\n\
+             </questiontext>\n\
+             <generalfeedback>\n\
+             <text></text>\n\
+             </generalfeedback>\n\
+             <shuffleanswers>true</shuffleanswers>\n\
+             {}\
+             </question>\n",
+            $q_num, $q_name, $subquestions
+        )
+        .as_bytes()
+    };
+}
+
+// Returns the words left out because no set could take them
+pub fn write_categories(
+    path: &Path,
+    categories: &[Category],
+    set_size: usize,
+) -> Result<LeftOut, Box<dyn Error>> {
+    let set_size = set_size.max(MIN_SET_SIZE);
+    let f = File::create(path)?;
+    let mut writer = BufWriter::new(f);
+    writer.write_all(moodle::XML_START.as_bytes())?;
+    let mut question_number: u32 = config::get().start_number;
+    let mut total_left_out = LeftOut::default();
+    for category in categories {
+        moodle::write_category(&mut writer, &category.path)?;
+        let (sets, left_out) = make_sets(&category.questions, set_size);
+        total_left_out.clashed += left_out.clashed;
+        total_left_out.too_few += left_out.too_few;
+        for set in sets {
+            question_number += 1;
+            let question_name = format!("{}_match_{}", category.basename, question_number);
+            let mut subquestions = String::new();
+            for pair in &set {
+                subquestions.push_str(&format!(
+                    "<subquestion format=\"html\">\n\
+                     <text><![CDATA[<p>{}</p>]]></text>\n\
+                     <answer>\n\
+                     <text>{}</text>\n\
+                     </answer>\n\
+                     </subquestion>\n",
+                    escape::cdata(&escape::xml_text(&pair.latin)),
+                    escape::xml_text(&pair.english)
+                ));
+            }
+            writer.write_all(xml_matching!(
+                question_number,
+                escape::xml_text(&question_name),
+                subquestions
+            ))?;
+        }
+    }
+    writer.write_all(b"</quiz>\n")?;
+    writer.flush()?;
+    Ok(total_left_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::question;
+
+    fn sizes(questions: &[Question], set_size: usize) -> (Vec<usize>, LeftOut) {
+        let questions = questions.iter().collect::<Vec<&Question>>();
+        let (sets, left_out) = make_sets(&questions, set_size);
+        (sets.iter().map(|set| set.len()).collect(), left_out)
+    }
+
+    fn words(count: usize) -> Vec<Question> {
+        (0..count)
+            .map(|n| question(&format!("latin{}", n), &format!("english{}", n)))
+            .collect()
+    }
+
+    #[test]
+    fn sets_keep_to_the_set_size() {
+        let (sizes, left_out) = sizes(&words(7), 5);
+        assert_eq!(sizes, [4, 3]);
+        assert_eq!(left_out, LeftOut::default());
+    }
+
+    #[test]
+    fn too_few_words_are_not_clashes() {
+        let (sizes, left_out) = sizes(&words(4), 3);
+        assert_eq!(sizes, [3]);
+        assert_eq!(
+            left_out,
+            LeftOut {
+                clashed: 0,
+                too_few: 1
+            }
+        );
+    }
+
+    #[test]
+    fn clashing_words_are_counted() {
+        // only one "and" can go in each set, and there is one set
+        let mut questions = words(3);
+        questions.push(question("et", "and"));
+        questions.push(question("atque", "and"));
+        questions.push(question("ac", "and"));
+        let (sizes, left_out) = sizes(&questions, 5);
+        assert_eq!(sizes, [4]);
+        assert_eq!(
+            left_out,
+            LeftOut {
+                clashed: 2,
+                too_few: 0
+            }
+        );
+    }
+}
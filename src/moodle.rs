@@ -0,0 +1,625 @@
+use crate::build::{Import, ImportError};
+use crate::config;
+use crate::escape;
+use crate::multichoice::{self, Choices, LeftOut};
+use crate::{AnswerOption, Category, Gap, GapType, Question};
+use regex::Regex;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Put opening statement in xml file
+pub(crate) const XML_START: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<quiz>\n";
+
+// Every question after this goes into the category
+// fields are stage_number, folder_name
+macro_rules! xml_category {
+    ($arg1:expr) => {
+        format!(
+            "<!-- question: 0  -->\n\
+             <question type=\"category\">\n\
+             <category>\n\
+             <text>$course$/{}</text>\n\
+             </category>\n\
+             </question>\n",
+            $arg1
+        )
+        .as_bytes()
+    };
+}
+
+// fields are question_number, question_name, question, font, questioncode
+macro_rules! xml_question {
+    ($q_num:expr, $q_name:expr, $question:expr, $font:expr, $q_code:expr) => {
+        format!(
+            "<!-- question: {}  -->\n\
+             <question type=\"cloze\" > \n\
+             <name>\n\
+             <text>{}</text>\n\
+             </name>\n\
+             <questiontext>\n\
+             <text>\n\
+             <![CDATA[<p>{}</p>\n\
+             <p><font size=\"4\" face=\"{}\">{}.</font></p>]]>\n\
+             </text>\n\
+             </questiontext>\n\
+             <generalfeedback>\n\
+             <text></text>\n\
+             </generalfeedback>\n\
+             <shuffleanswers>0</shuffleanswers>\n\
+             </question>\n",
+            $q_num, $q_name, $question, $font, $q_code
+        )
+        .as_bytes()
+    };
+}
+
+pub fn write_categories(path: &Path, categories: &[Category]) -> Result<(), Box<dyn Error>> {
+    let xml_end: String = String::from("</quiz>\n");
+
+    // set up Writer
+    let f = File::create(path)?;
+    let mut writer = BufWriter::new(f);
+
+    // make initial write
+    writer.write_all(XML_START.as_bytes())?;
+
+    let config = config::get();
+    let font = escape::cdata(&escape::xml_text(&config.font));
+    let mut question_number: u32 = config.start_number;
+    let mut question_name: String;
+    let mut question_word: String;
+    let mut question_code: String;
+    for category in categories {
+        write_category(&mut writer, &category.path)?;
+        //Now loop over question data
+        for question in category.questions.iter() {
+            question_number += 1;
+            question_name = format!("{}_q_{}", category.basename, question_number);
+            question_name = escape::xml_text(&question_name);
+            // the question text is HTML inside a CDATA section
+            question_word = escape::cdata(&escape::xml_text(&question.latin));
+            question_code = escape::cdata(&format!(" {}", moodle_cloze(question)));
+            writer.write_all(xml_question!(
+                question_number,
+                question_name,
+                question_word,
+                font,
+                question_code
+            ))?;
+        }
+    }
+    writer.write_all(xml_end.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Moodle only accepts these fractions for a multiple choice answer
+const FRACTIONS: [f32; 21] = [
+    100.0, 90.0, 83.33333, 80.0, 75.0, 70.0, 66.66667, 60.0, 50.0, 40.0, 33.33333, 30.0, 25.0,
+    20.0, 16.66667, 14.28571, 12.5, 11.11111, 10.0, 5.0, 0.0,
+];
+
+// The allowed fraction nearest to a mark. Negative fractions take marks
+// away, for questions with more than one right answer.
+fn fraction(mark: f32) -> f32 {
+    let mut nearest = FRACTIONS[0];
+    for allowed in FRACTIONS.iter() {
+        if (allowed - mark.abs()).abs() < (nearest - mark.abs()).abs() {
+            nearest = *allowed;
+        }
+    }
+    match mark < 0.0 {
+        true => -nearest,
+        false => nearest,
+    }
+}
+
+// fields are question_number, question_name, question, single, answers
+macro_rules! xml_multichoice {
+    ($q_num:expr, $q_name:expr, $question:expr, $single:expr, $answers:expr) => {
+        format!(
+            "<!-- question: {}  -->\n\
+             <question type=\"multichoice\">\n\
+             <name>\n\
+             <text>{}</text>\n\
+             </name>\n\
+             <questiontext format=\"html\">\n\
+             <text><![CDATA[<p>{}</p>]]></text>\n\
+             </questiontext>\n\
+             <generalfeedback>\n\
+             <text></text>\n\
+             </generalfeedback>\n\
+             <single>{}</single>\n\
+             <shuffleanswers>true</shuffleanswers>\n\
+             <answernumbering>abc</answernumbering>\n\
+             {}\
+             </question>\n",
+            $q_num, $q_name, $question, $single, $answers
+        )
+        .as_bytes()
+    };
+}
+
+// Native multiple choice questions, for themes where Cloze menus don't show
+// well. The menus come from multichoice::menus. When more than one answer is
+// right, e.g. masculine and feminine for a common noun, the question takes
+// several answers and the marks are shared between them. Returns the
+// questions that were left out.
+pub fn write_multichoice_categories(
+    path: &Path,
+    categories: &[Category],
+    choices: &Choices,
+) -> Result<LeftOut, Box<dyn Error>> {
+    let f = File::create(path)?;
+    let mut writer = BufWriter::new(f);
+    writer.write_all(XML_START.as_bytes())?;
+    let mut question_number: u32 = config::get().start_number;
+    let mut left_out = LeftOut::default();
+    for category in categories {
+        write_category(&mut writer, &category.path)?;
+        let (questions, typed_gaps) = multichoice::one_gap_each(&category.questions);
+        left_out.typed_gaps += typed_gaps;
+        let questions = questions.iter().collect::<Vec<&Question>>();
+        let menus = multichoice::menus(&questions, choices);
+        for (question, menu) in questions.iter().zip(menus) {
+            let menu = match menu {
+                Some(menu) => menu,
+                None => {
+                    left_out.no_menu += 1;
+                    continue;
+                }
+            };
+            question_number += 1;
+            let question_name = format!("{}_q_{}", category.basename, question_number);
+            let right = menu.iter().filter(|a| a.mark == 100).count().max(1);
+            let mut answers = String::new();
+            for answer in &menu {
+                // with several right answers, ticking a wrong one costs as
+                // much as a right one earns
+                let mark = match answer.mark {
+                    100 => 100.0 / right as f32,
+                    0 if right > 1 => -100.0 / right as f32,
+                    mark if right > 1 => mark as f32 / right as f32,
+                    mark => mark as f32,
+                };
+                answers.push_str(&format!(
+                    "<answer fraction=\"{}\" format=\"html\">\n\
+                     <text>{}</text>\n\
+                     <feedback format=\"html\">\n\
+                     <text>{}</text>\n\
+                     </feedback>\n\
+                     </answer>\n",
+                    fraction(mark),
+                    escape::xml_text(&answer.answer),
+                    escape::xml_text(&answer.feedback)
+                ));
+            }
+            writer.write_all(xml_multichoice!(
+                question_number,
+                escape::xml_text(&question_name),
+                escape::cdata(&escape::xml_text(&question.latin)),
+                right == 1,
+                answers
+            ))?;
+        }
+    }
+    writer.write_all(b"</quiz>\n")?;
+    writer.flush()?;
+    Ok(left_out)
+}
+
+// Start a category, which every question written after it goes into
+pub(crate) fn write_category(writer: &mut dyn Write, path: &str) -> io::Result<()> {
+    writer.write_all(xml_category!(escape::xml_text(path)))
+}
+
+// The Cloze code for all of a question's gaps, with each extra gap's prompt before it
+pub fn moodle_cloze(question: &Question) -> String {
+    let mut code = moodle_gap(question.gap_type, &question.answers);
+    for gap in &question.extra_gaps {
+        code = format!(
+            "{} {} {}",
+            code,
+            escape::xml_text(&gap.prompt),
+            moodle_gap(gap.gap_type, &gap.answers)
+        );
+    }
+    code
+}
+
+pub fn moodle_gap(gap_type: GapType, answers: &[AnswerOption]) -> String {
+    match gap_type {
+        GapType::ShortAnswer => moodle_shortanswer(answers),
+        GapType::MultiChoice => moodle_multichoice(answers),
+    }
+}
+
+fn cloze_answers(answers: &[AnswerOption]) -> String {
+    let mut question_string = String::new();
+    for answer in answers {
+        question_string = format!(
+            "{}~%{}%{}#{}",
+            question_string,
+            answer.mark,
+            escape::cloze(&answer.answer),
+            escape::cloze(&answer.feedback)
+        );
+    }
+    question_string
+}
+
+pub fn moodle_shortanswer(answers: &[AnswerOption]) -> String {
+    format!("{{1:SHORTANSWER:{}}}", cloze_answers(answers))
+}
+
+// A drop-down menu of the answers, in the order given
+pub fn moodle_multichoice(answers: &[AnswerOption]) -> String {
+    format!("{{1:MULTICHOICE:{}}}", cloze_answers(answers))
+}
+
+// Reading a Moodle XML question bank back in, so that old quizzes can be
+// fixed in the editor and exported again. Cloze questions are read into a
+// Question each; other question types are listed as skipped.
+pub fn read_xml(path: &Path) -> Result<Import, ImportError> {
+    let text =
+        fs::read_to_string(path).map_err(|e| ImportError::new(path, None, None, &e.to_string()))?;
+    let doc = roxmltree::Document::parse(&text)
+        .map_err(|e| ImportError::new(path, Some(e.pos().row as u64), None, &e.to_string()))?;
+    let mut import = Import::default();
+    let mut test: Option<u32> = None;
+    for node in doc.descendants().filter(|n| n.has_tag_name("question")) {
+        let line = doc.text_pos_at(node.range().start).row as u64;
+        let name = child_text(node, &["name", "text"]);
+        let skip = |reason: &str| ImportError::new(path, Some(line), name.clone(), reason);
+        match node.attribute("type") {
+            Some("category") => {
+                let category = child_text(node, &["category", "text"]).unwrap_or_default();
+                test = test_number(&category);
+            }
+            Some("cloze") => {
+                let html = child_text(node, &["questiontext", "text"]).unwrap_or_default();
+                match read_cloze(&html) {
+                    Ok(mut question) => {
+                        question.test = test;
+                        import.questions.push(question);
+                    }
+                    Err(reason) => import.skipped.push(skip(&reason)),
+                }
+            }
+            Some(other) => import
+                .skipped
+                .push(skip(&format!("{} questions can't be read", other))),
+            None => import.skipped.push(skip("question with no type")),
+        }
+    }
+    Ok(import)
+}
+
+// The text of the element found by following `path` down from `node`,
+// e.g. name/text. CDATA sections count as text.
+fn child_text(node: roxmltree::Node, path: &[&str]) -> Option<String> {
+    let mut node = node;
+    for tag in path {
+        node = node.children().find(|n| n.has_tag_name(*tag))?;
+    }
+    Some(
+        node.children()
+            .filter(|n| n.is_text())
+            .filter_map(|n| n.text())
+            .collect(),
+    )
+}
+
+// The test a category is for, from the number at the end, e.g. 20 for
+// $course$/top/Vocabulary/A_20
+fn test_number(category: &str) -> Option<u32> {
+    let name = category.trim_end_matches('/').rsplit('/').next()?;
+    let digits = name
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    digits.chars().rev().collect::<String>().parse().ok()
+}
+
+// Text out of question HTML: tags removed and entities replaced
+fn html_text(html: &str) -> String {
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let text = tags.replace_all(html, " ").replace("&nbsp;", " ");
+    let text = escape::unescape_xml_text(&text);
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// A question's gaps and the text around them. The text before the first gap
+// is the Latin, and the text before each later gap is that gap's prompt, as
+// written by moodle_cloze.
+fn read_cloze(html: &str) -> Result<Question, String> {
+    let mut texts: Vec<String> = Vec::new();
+    let mut gaps: Vec<(GapType, Vec<AnswerOption>)> = Vec::new();
+    let mut rest = html;
+    while let Some(start) = find_gap(rest) {
+        let end = gap_end(&rest[start..]).ok_or("a gap is not closed with }")?;
+        texts.push(html_text(&rest[..start]));
+        gaps.push(read_gap(&rest[start + 1..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    if gaps.is_empty() {
+        return Err("no gaps in the question".to_string());
+    }
+    let mut gaps = gaps.into_iter().zip(texts);
+    let ((gap_type, answers), latin) = gaps.next().unwrap();
+    Ok(Question {
+        gap_type,
+        extra_gaps: gaps
+            .map(|((gap_type, answers), prompt)| Gap {
+                prompt,
+                gap_type,
+                answers,
+            })
+            .collect(),
+        ..Question::new(latin.trim_end_matches('.').trim().to_string(), answers)
+    })
+}
+
+// Gaps can be given any weight, e.g. {2:SHORTANSWER:...}, or none, {:SA:...}
+fn find_gap(s: &str) -> Option<usize> {
+    let gap = Regex::new(r"\{\d*:[A-Z_]+:").unwrap();
+    gap.find(s).map(|m| m.start())
+}
+
+// Where the gap starting at the beginning of `s` ends, skipping \}
+fn gap_end(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (num, c) in s.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '}' if !escaped => return Some(num),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+// Split on a character that isn't escaped with a backslash
+fn split_unescaped(s: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (num, c) in s.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c if c == separator && !escaped => {
+                parts.push(&s[start..num]);
+                start = num + c.len_utf8();
+            }
+            _ => escaped = false,
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+// One gap without its braces, e.g. 1:SHORTANSWER:~%100%I carry#Well done!
+fn read_gap(gap: &str) -> Result<(GapType, Vec<AnswerOption>), String> {
+    let mut fields = gap.splitn(3, ':');
+    let (_weight, kind, answers) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(weight), Some(kind), Some(answers)) => (weight, kind, answers),
+        _ => return Err(format!("'{{{}}}' is not a Cloze gap", gap)),
+    };
+    let gap_type = match kind {
+        "SHORTANSWER" | "SA" | "MW" | "SHORTANSWER_C" | "SAC" | "MWC" => GapType::ShortAnswer,
+        kind if kind.starts_with("MULTICHOICE") || kind.starts_with("MC") => GapType::MultiChoice,
+        kind => return Err(format!("{} gaps can't be read", kind)),
+    };
+    let mut options: Vec<AnswerOption> = Vec::new();
+    for answer in split_unescaped(answers, '~') {
+        if answer.is_empty() {
+            continue;
+        }
+        let (mark, answer) = if let Some(answer) = answer.strip_prefix('=') {
+            (100, answer)
+        } else if let Some(marked) = answer.strip_prefix('%') {
+            let mut marked = marked.splitn(2, '%');
+            let mark = marked.next().unwrap_or("");
+            let mark = mark
+                .parse::<f32>()
+                .map_err(|_| format!("'{}' is not a mark", mark))?;
+            (
+                mark.round().clamp(0.0, 100.0) as u8,
+                marked.next().unwrap_or(""),
+            )
+        } else {
+            (0, answer)
+        };
+        let mut parts = split_unescaped(answer, '#').into_iter();
+        let text = parts.next().unwrap_or("");
+        let feedback = parts.collect::<Vec<&str>>().join("#");
+        // answers are written with only their Cloze characters escaped
+        options.push(AnswerOption {
+            mark,
+            answer: escape::unescape_cloze(text),
+            feedback: escape::unescape_cloze(&feedback),
+        });
+    }
+    Ok((gap_type, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escape::AWKWARD;
+    use crate::test_helpers::{temp_path, written};
+
+    fn answer(mark: u8, answer: &str, feedback: &str) -> AnswerOption {
+        AnswerOption {
+            mark,
+            answer: answer.to_string(),
+            feedback: feedback.to_string(),
+        }
+    }
+
+    fn awkward_question(awkward: &str) -> Question {
+        Question {
+            extra_gaps: vec![
+                Gap {
+                    prompt: format!("prompt {}", awkward).trim().to_string(),
+                    gap_type: GapType::ShortAnswer,
+                    answers: vec![answer(50, awkward, "")],
+                },
+                Gap {
+                    prompt: "gender".to_string(),
+                    gap_type: GapType::MultiChoice,
+                    answers: vec![answer(100, "masculine", "Yes"), answer(0, "feminine", "")],
+                },
+            ],
+            ..Question::new(
+                format!("latin {}", awkward).trim().to_string(),
+                vec![
+                    answer(100, &format!("right {}", awkward), awkward),
+                    answer(0, awkward, &format!("wrong {}", awkward)),
+                ],
+            )
+        }
+    }
+
+    #[test]
+    fn cloze_round_trips() {
+        let questions = AWKWARD
+            .iter()
+            .map(|a| awkward_question(a))
+            .collect::<Vec<Question>>();
+        let path = temp_path("cloze_round_trip.xml");
+        write_categories(&path, &[Category::single("top/A_20", "A_20", &questions)]).unwrap();
+        let import = read_xml(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(import.skipped.is_empty());
+        assert_eq!(import.questions.len(), questions.len());
+        for (read, written) in import.questions.iter().zip(&questions) {
+            assert_eq!(read.latin, written.latin);
+            assert_eq!(read.test, Some(20));
+            assert_eq!(read.gap_type, written.gap_type);
+            assert_eq!(
+                format!("{:?}", read.answers),
+                format!("{:?}", written.answers)
+            );
+            assert_eq!(
+                format!("{:?}", read.extra_gaps),
+                format!("{:?}", written.extra_gaps)
+            );
+        }
+    }
+
+    #[test]
+    fn gaps_without_a_weight() {
+        let question = read_cloze("<p>porto</p> {:SA:=I carry#Yes} and {:MC:~=a~b}").unwrap();
+        assert_eq!(question.latin, "porto");
+        assert_eq!(question.answers[0].answer, "I carry");
+        assert_eq!(question.answers[0].mark, 100);
+        assert_eq!(question.extra_gaps[0].prompt, "and");
+        assert_eq!(question.extra_gaps[0].gap_type, GapType::MultiChoice);
+        assert_eq!(question.extra_gaps[0].answers.len(), 2);
+    }
+
+    #[test]
+    fn only_cloze_gaps_are_read() {
+        assert!(read_cloze("<p>porto</p> {1:NUMERICAL:=4}").is_err());
+        assert!(read_cloze("<p>porto</p> no gaps").is_err());
+        assert!(read_cloze("<p>porto</p> {1:SA:=open").is_err());
+    }
+
+    #[test]
+    fn fractions_are_ones_moodle_allows() {
+        assert_eq!(fraction(100.0), 100.0);
+        assert_eq!(fraction(50.0), 50.0);
+        assert_eq!(fraction(-50.0), -50.0);
+        assert_eq!(fraction(100.0 / 3.0), 33.33333);
+        assert_eq!(fraction(-100.0 / 3.0), -33.33333);
+        assert_eq!(fraction(100.0 / 7.0), 14.28571);
+        assert_eq!(fraction(1.0), 0.0);
+        assert_eq!(fraction(120.0), 100.0);
+    }
+
+    fn fractions_written(right: usize, wrong: usize) -> (Vec<String>, String) {
+        let mut answers = Vec::new();
+        for num in 0..right {
+            answers.push(answer(100, &format!("right{}", num), ""));
+        }
+        for num in 0..wrong {
+            answers.push(answer(0, &format!("wrong{}", num), ""));
+        }
+        let questions = [Question {
+            gap_type: GapType::MultiChoice,
+            ..Question::new("civis".to_string(), answers)
+        }];
+        let choices = Choices::default();
+        let (_, xml) = written(&format!("multichoice_{}.xml", right), |path| {
+            write_multichoice_categories(
+                path,
+                &[Category::single("top", "A", &questions)],
+                &choices,
+            )
+            .unwrap()
+        });
+        let fraction = Regex::new(r#"fraction="([^"]*)""#).unwrap();
+        let mut fractions = fraction
+            .captures_iter(&xml)
+            .map(|c| c[1].to_string())
+            .collect::<Vec<String>>();
+        fractions.sort();
+        let single = Regex::new(r"<single>(.*)</single>").unwrap();
+        (fractions, single.captures(&xml).unwrap()[1].to_string())
+    }
+
+    #[test]
+    fn one_right_answer() {
+        assert_eq!(
+            fractions_written(1, 2),
+            (
+                vec!["0".to_string(), "0".to_string(), "100".to_string()],
+                "true".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn marks_are_shared_between_right_answers() {
+        assert_eq!(
+            fractions_written(2, 2),
+            (
+                vec![
+                    "-50".to_string(),
+                    "-50".to_string(),
+                    "50".to_string(),
+                    "50".to_string()
+                ],
+                "false".to_string()
+            )
+        );
+        let (fractions, single) = fractions_written(3, 1);
+        assert_eq!(fractions, ["-33.33333", "33.33333", "33.33333", "33.33333"]);
+        assert_eq!(single, "false");
+    }
+
+    #[test]
+    fn typed_gaps_are_left_out_and_menu_gaps_split() {
+        let mut principal_parts = awkward_question("porto");
+        principal_parts.extra_gaps.remove(1);
+        let mut preposition = awkward_question("pro");
+        preposition.extra_gaps.remove(0);
+        let questions = [principal_parts, preposition];
+        let (left_out, xml) = written("multichoice_gaps.xml", |path| {
+            write_multichoice_categories(
+                path,
+                &[Category::single("top", "A", &questions)],
+                &Choices::default(),
+            )
+            .unwrap()
+        });
+        assert_eq!(left_out.typed_gaps, 1);
+        assert_eq!(left_out.no_menu, 0);
+        assert_eq!(xml.matches("<question type=\"multichoice\">").count(), 2);
+        assert!(xml.contains("<p>latin pro gender</p>"));
+    }
+}
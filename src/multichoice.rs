@@ -0,0 +1,392 @@
+use crate::pos::PartOfSpeech;
+use crate::{AnswerOption, GapType, Question};
+use std::mem;
+
+// Multiple choice questions for younger classes: the typed English answer
+// becomes a drop-down menu, with the wrong options taken from the meanings
+// of other words of the same part of speech in the list.
+
+#[derive(Debug, Clone)]
+pub struct Choices {
+    // options in each menu, counting the right one
+    pub count: usize,
+    // the same seed always gives the same menus, so a test can be rebuilt
+    pub seed: u64,
+}
+
+impl Default for Choices {
+    fn default() -> Self {
+        Choices { count: 4, seed: 1 }
+    }
+}
+
+// Questions a native multiple choice export had to leave out
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LeftOut {
+    // no menu the format can take could be made
+    pub no_menu: usize,
+    // more than one typed gap, like a verb's principal parts
+    pub typed_gaps: usize,
+}
+
+// A small random number generator (splitmix64), so that a seed gives the
+// same order on every machine
+struct Shuffler {
+    state: u64,
+}
+
+impl Shuffler {
+    fn new(seed: u64) -> Shuffler {
+        Shuffler { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+// The answer a question is looking for: its first full-mark answer
+fn right_answer(question: &Question) -> Option<&AnswerOption> {
+    question.answers.iter().find(|a| a.mark == 100)
+}
+
+// Wrong answers already in a question that make sense in a menu, like
+// "I am tried" for conor. Wildcards like *carry* don't.
+fn own_traps(question: &Question) -> Vec<AnswerOption> {
+    question
+        .answers
+        .iter()
+        .filter(|a| a.mark == 0 && !a.answer.contains('*'))
+        .cloned()
+        .collect()
+}
+
+// Another word's (or part's) meaning as a wrong option
+fn wrong_option(other: &Question) -> Option<AnswerOption> {
+    right_answer(other).map(|a| AnswerOption {
+        mark: 0,
+        answer: a.answer.clone(),
+        feedback: format!("No, that is {}.", other.latin),
+    })
+}
+
+// Turn the typed questions of each word into multiple choice. `words` gives
+// the part of speech of each word and how many questions it has, in the
+// order the questions are in. A question's wrong options come from the
+// question in the same place for other words of that part of speech, so
+// a perfect is offered other perfects. Verbs are offered their own other
+// parts first, e.g. "I carry" on the question for portavi.
+pub fn apply(questions: &mut [Question], words: &[(PartOfSpeech, usize)], choices: &Choices) {
+    let mut starts: Vec<usize> = Vec::new();
+    let mut start = 0;
+    for (_, count) in words {
+        starts.push(start);
+        start += count;
+    }
+    let originals = questions.to_vec();
+    let mut shuffler = Shuffler::new(choices.seed);
+    for (word, (part_of_speech, count)) in words.iter().enumerate() {
+        for place in 0..*count {
+            let num = starts[word] + place;
+            let question = &originals[num];
+            if question.gap_type != GapType::ShortAnswer || question.has_typed_extra_gaps() {
+                continue;
+            }
+            let right = match right_answer(question) {
+                Some(right) => right.clone(),
+                None => continue,
+            };
+            let is_right = |answer: &str| {
+                question
+                    .answers
+                    .iter()
+                    .any(|a| a.mark == 100 && a.answer == answer)
+            };
+            let mut wrong = own_traps(question);
+            if part_of_speech.is_verb() {
+                let mut own = (0..*count)
+                    .filter(|other| *other != place)
+                    .map(|other| &originals[starts[word] + other])
+                    .filter_map(wrong_option)
+                    .collect::<Vec<AnswerOption>>();
+                shuffler.shuffle(&mut own);
+                // leave room for at least one other verb
+                own.truncate(choices.count.saturating_sub(2).max(1));
+                wrong.append(&mut own);
+            }
+            let mut others = words
+                .iter()
+                .enumerate()
+                .filter(|(other, (other_pos, other_count))| {
+                    *other != word
+                        && mem::discriminant(other_pos) == mem::discriminant(part_of_speech)
+                        && place < *other_count
+                })
+                .map(|(other, _)| &originals[starts[other] + place])
+                .filter(|other| {
+                    other.gap_type == GapType::ShortAnswer && !other.has_typed_extra_gaps()
+                })
+                .filter_map(wrong_option)
+                .collect::<Vec<AnswerOption>>();
+            shuffler.shuffle(&mut others);
+            wrong.append(&mut others);
+
+            let mut options: Vec<AnswerOption> = vec![right];
+            for option in wrong {
+                if options.len() >= choices.count {
+                    break;
+                }
+                if is_right(&option.answer) || options.iter().any(|o| o.answer == option.answer) {
+                    continue;
+                }
+                options.push(option);
+            }
+            // a menu with only the right answer in it would give the game away
+            if options.len() < 2 {
+                continue;
+            }
+            shuffler.shuffle(&mut options);
+            questions[num].answers = options;
+            questions[num].gap_type = GapType::MultiChoice;
+        }
+    }
+}
+
+// What kind of English an answer is, from its first word, e.g. "I carry"
+// and "I teach" are both "i" and "to carry" is "to". At export time the
+// part of speech is no longer known, so this keeps the wrong options in
+// the same form as the right one.
+fn shape(answer: &str) -> String {
+    let first = answer
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    match first.as_str() {
+        "i" | "to" | "having" | "of" => first,
+        _ => String::new(),
+    }
+}
+
+// A menu of options for each question, for exports that only have multiple
+// choice questions. Questions that are already a menu keep their options.
+// See one_gap_each for questions with more than one gap.
+// Typed questions keep their own wrong answers, like "I carry" on portavi,
+// with their marks and feedback, and are given the English of other
+// questions of the same shape to make up the count. A question that can't
+// be given at least one wrong option has no menu.
+pub fn menus(questions: &[&Question], choices: &Choices) -> Vec<Option<Vec<AnswerOption>>> {
+    let mut shuffler = Shuffler::new(choices.seed);
+    let mut menus: Vec<Option<Vec<AnswerOption>>> = Vec::new();
+    for (num, question) in questions.iter().enumerate() {
+        if question.gap_type == GapType::MultiChoice {
+            menus.push(Some(question.answers.clone()));
+            continue;
+        }
+        let right = match right_answer(question) {
+            Some(right) => right.clone(),
+            None => {
+                menus.push(None);
+                continue;
+            }
+        };
+        let mut options: Vec<AnswerOption> = vec![right.clone()];
+        let own = question
+            .answers
+            .iter()
+            .filter(|a| a.mark < 100 && !a.answer.contains('*'))
+            .cloned();
+        let mut others = questions
+            .iter()
+            .enumerate()
+            .filter(|(other, q)| {
+                *other != num
+                    && q.gap_type == GapType::ShortAnswer
+                    && right_answer(q).map(|a| shape(&a.answer)) == Some(shape(&right.answer))
+            })
+            .filter_map(|(_, q)| wrong_option(q))
+            .collect::<Vec<AnswerOption>>();
+        shuffler.shuffle(&mut others);
+        for option in own.chain(others) {
+            if options.len() >= choices.count {
+                break;
+            }
+            if question
+                .answers
+                .iter()
+                .any(|a| a.mark == 100 && a.answer == option.answer)
+                || options.iter().any(|o| o.answer == option.answer)
+            {
+                continue;
+            }
+            options.push(option);
+        }
+        if options.len() < 2 {
+            menus.push(None);
+            continue;
+        }
+        shuffler.shuffle(&mut options);
+        menus.push(Some(options));
+    }
+    menus
+}
+
+// Native multiple choice questions have only one answer each, so a question
+// whose extra gaps are menus, e.g. "pro + which case?", becomes a question
+// for each gap with its prompt after the Latin. Questions with typed extra
+// gaps can't be asked this way and are only counted.
+pub fn one_gap_each(questions: &[&Question]) -> (Vec<Question>, usize) {
+    let mut split: Vec<Question> = Vec::new();
+    let mut typed_gaps = 0;
+    for question in questions {
+        if question.has_typed_extra_gaps() {
+            typed_gaps += 1;
+            continue;
+        }
+        split.push(Question {
+            extra_gaps: Vec::new(),
+            ..(*question).clone()
+        });
+        for gap in &question.extra_gaps {
+            split.push(Question {
+                test: question.test,
+                gap_type: gap.gap_type,
+                reversed: question.reversed,
+                ..Question::new(
+                    format!("{} {}", question.latin, gap.prompt),
+                    gap.answers.clone(),
+                )
+            });
+        }
+    }
+    (split, typed_gaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::{build_verb, WrongForms};
+    use crate::test_helpers::question;
+    use crate::{verb_cache, Gap};
+
+    fn meanings(menu: &[AnswerOption]) -> Vec<&str> {
+        menu.iter().map(|a| a.answer.as_str()).collect()
+    }
+
+    #[test]
+    fn menus_offer_the_same_shape() {
+        let questions = [
+            question("porto", "I carry"),
+            question("doceo", "I teach"),
+            question("traho", "I drag"),
+            question("portare", "to carry"),
+            question("docere", "to teach"),
+        ];
+        let refs = questions.iter().collect::<Vec<&Question>>();
+        let menus = menus(&refs, &Choices::default());
+        let first = menus[0].as_ref().unwrap();
+        assert_eq!(first.len(), 3);
+        assert_eq!(first.iter().filter(|a| a.mark == 100).count(), 1);
+        assert!(meanings(first).iter().all(|m| m.starts_with("I ")));
+        let infinitive = menus[3].as_ref().unwrap();
+        let mut offered = meanings(infinitive);
+        offered.sort();
+        assert_eq!(offered, ["to carry", "to teach"]);
+    }
+
+    #[test]
+    fn same_seed_same_menus() {
+        let questions = (0..10)
+            .map(|n| question(&format!("latin{}", n), &format!("english {}", n)))
+            .collect::<Vec<Question>>();
+        let refs = questions.iter().collect::<Vec<&Question>>();
+        let choices = Choices { count: 4, seed: 7 };
+        let once = format!("{:?}", menus(&refs, &choices));
+        assert_eq!(once, format!("{:?}", menus(&refs, &choices)));
+        let other = Choices { count: 4, seed: 8 };
+        assert_ne!(once, format!("{:?}", menus(&refs, &other)));
+    }
+
+    #[test]
+    fn nothing_to_offer_is_no_menu() {
+        let questions = [question("et", "and"), question("atque", "and")];
+        let refs = questions.iter().collect::<Vec<&Question>>();
+        assert!(menus(&refs, &Choices::default())
+            .iter()
+            .all(|menu| menu.is_none()));
+    }
+
+    #[test]
+    fn gaps_each_get_a_question() {
+        let mut preposition = question("pro", "instead of");
+        preposition.extra_gaps.push(Gap {
+            prompt: "+ which case?".to_string(),
+            gap_type: GapType::MultiChoice,
+            answers: vec![],
+        });
+        let mut principal_parts = question("porto,", "portare");
+        principal_parts.extra_gaps.push(Gap {
+            prompt: "— meaning".to_string(),
+            gap_type: GapType::ShortAnswer,
+            answers: vec![],
+        });
+        let (split, typed_gaps) = one_gap_each(&[&preposition, &principal_parts]);
+        assert_eq!(typed_gaps, 1);
+        let latin = split
+            .iter()
+            .map(|q| q.latin.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(latin, ["pro", "pro + which case?"]);
+        assert_eq!(split[1].gap_type, GapType::MultiChoice);
+    }
+
+    #[test]
+    fn verbs_are_offered_their_own_parts_first() {
+        verb_cache::add_override("carry", "carry", "carried", "carried");
+        verb_cache::add_override("teach", "teach", "taught", "taught");
+        let verb = PartOfSpeech::Verb {
+            conjugation: None,
+            voice: None,
+        };
+        let mut questions = Vec::new();
+        for (latin, english) in [
+            ("porto, portare, portavi, portatus", "I carry"),
+            ("doceo, docere, docui, doctus", "I teach"),
+        ] {
+            questions.extend(build_verb(
+                latin.to_string(),
+                english.to_string(),
+                &verb,
+                &WrongForms::default(),
+            ));
+        }
+        let words = [(verb.clone(), 4), (verb, 4)];
+        apply(&mut questions, &words, &Choices { count: 5, seed: 1 });
+        let portavi = &questions[2];
+        assert_eq!(portavi.latin, "portavi");
+        assert_eq!(portavi.gap_type, GapType::MultiChoice);
+        let mut offered = meanings(&portavi.answers);
+        offered.sort();
+        assert_eq!(
+            offered,
+            [
+                "I carried",
+                "I carry",
+                "I taught",
+                "having been carried",
+                "to carry"
+            ]
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use std::fmt;
+use std::str::FromStr;
+
+// The "Part of Speech" column, e.g. "noun 3 f", "adj 212", "verb dep" or "prep".
+// Anything that can't be understood is an error rather than a guess.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartOfSpeech {
+    Noun {
+        declension: Option<u8>,
+        gender: Option<Gender>,
+    },
+    Adjective {
+        pattern: Option<AdjectivePattern>,
+    },
+    Verb {
+        conjugation: Option<u8>,
+        voice: Option<Voice>,
+    },
+    Adverb,
+    Conjunction,
+    Preposition,
+    Pronoun,
+    Interjection,
+    Numeral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gender {
+    Masculine,
+    Feminine,
+    Neuter,
+    // m/f, e.g. civis
+    Common,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdjectivePattern {
+    // bonus, bona, bonum: 2-1-2
+    FirstSecond,
+    // ingens, ingentis: third declension, with 1, 2 or 3 terminations if known
+    Third { terminations: Option<u8> },
+}
+
+// Deponent verbs (conor, conari, conatus sum) are passive in form throughout,
+// semi-deponents (audeo, audere, ausus sum) only in the perfect. Both have
+// active meanings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Voice {
+    Active,
+    Deponent,
+    SemiDeponent,
+}
+
+// The case a preposition (or a verb like persuadeo DAT) takes, marked in the
+// English of a vocab list with a capitalised abbreviation, e.g. "instead of ABL"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Case {
+    Nominative,
+    Vocative,
+    Accusative,
+    Genitive,
+    Dative,
+    Ablative,
+}
+
+impl Case {
+    pub fn from_marker(marker: &str) -> Option<Case> {
+        match marker {
+            "NOM" => Some(Case::Nominative),
+            "VOC" => Some(Case::Vocative),
+            "ACC" => Some(Case::Accusative),
+            "GEN" => Some(Case::Genitive),
+            "DAT" => Some(Case::Dative),
+            "ABL" => Some(Case::Ablative),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Case::Nominative => "nominative",
+            Case::Vocative => "vocative",
+            Case::Accusative => "accusative",
+            Case::Genitive => "genitive",
+            Case::Dative => "dative",
+            Case::Ablative => "ablative",
+        }
+    }
+}
+
+// Every case marker in a piece of English, in order and without repeats
+pub fn case_markers(english: &str) -> Vec<Case> {
+    let mut cases: Vec<Case> = Vec::new();
+    for word in english.split(|c: char| !c.is_ascii_alphabetic()) {
+        if let Some(case) = Case::from_marker(word) {
+            if !cases.contains(&case) {
+                cases.push(case);
+            }
+        }
+    }
+    cases
+}
+
+impl PartOfSpeech {
+    pub fn is_verb(&self) -> bool {
+        matches!(self, PartOfSpeech::Verb { .. })
+    }
+}
+
+impl FromStr for PartOfSpeech {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PartOfSpeech, String> {
+        let tag = s.trim().to_lowercase();
+        let mut words = tag.split_whitespace();
+        let unknown = |word: &str| Err(format!("'{}' not understood in '{}'", word, s.trim()));
+        let kind = match words.next() {
+            Some(kind) => kind.trim_end_matches('.'),
+            None => return Err("no part of speech".to_string()),
+        };
+        let rest = words.collect::<Vec<&str>>();
+        let pos = match kind {
+            "noun" | "n" => {
+                let mut declension = None;
+                let mut gender = None;
+                for word in rest {
+                    match word.trim_end_matches('.') {
+                        d @ "1" | d @ "2" | d @ "3" | d @ "4" | d @ "5" => {
+                            declension = d.parse::<u8>().ok()
+                        }
+                        "m" => gender = Some(Gender::Masculine),
+                        "f" => gender = Some(Gender::Feminine),
+                        "n" => gender = Some(Gender::Neuter),
+                        "c" | "m/f" | "mf" | "f/m" => gender = Some(Gender::Common),
+                        other => return unknown(other),
+                    }
+                }
+                PartOfSpeech::Noun { declension, gender }
+            }
+            "adj" | "adjective" => {
+                let pattern = match rest.as_slice() {
+                    [] => None,
+                    ["212"] | ["2-1-2"] | ["12"] => Some(AdjectivePattern::FirstSecond),
+                    ["3"] => Some(AdjectivePattern::Third { terminations: None }),
+                    ["3", t] => match *t {
+                        "1" | "2" | "3" => Some(AdjectivePattern::Third {
+                            terminations: t.parse::<u8>().ok(),
+                        }),
+                        other => return unknown(other),
+                    },
+                    [other, ..] => return unknown(other),
+                };
+                PartOfSpeech::Adjective { pattern }
+            }
+            "verb" | "v" => {
+                let mut conjugation = None;
+                let mut voice = None;
+                for word in rest {
+                    match word.trim_end_matches('.') {
+                        c @ "1" | c @ "2" | c @ "3" | c @ "4" => conjugation = c.parse::<u8>().ok(),
+                        // mixed conjugation, e.g. capio
+                        "3io" | "3-io" | "5" => conjugation = Some(3),
+                        "dep" | "deponent" => voice = Some(Voice::Deponent),
+                        "semi-dep" | "semidep" | "semi-deponent" | "semideponent" => {
+                            voice = Some(Voice::SemiDeponent)
+                        }
+                        "irreg" | "irregular" => (),
+                        other => return unknown(other),
+                    }
+                }
+                PartOfSpeech::Verb { conjugation, voice }
+            }
+            "adv" | "adverb" => PartOfSpeech::Adverb,
+            "conj" | "conjunction" => PartOfSpeech::Conjunction,
+            "prep" | "preposition" => PartOfSpeech::Preposition,
+            "pronoun" | "pron" => PartOfSpeech::Pronoun,
+            "interj" | "interjection" => PartOfSpeech::Interjection,
+            "num" | "numeral" => PartOfSpeech::Numeral,
+            other => return Err(format!("unknown part of speech '{}'", other)),
+        };
+        // only nouns, adjectives and verbs carry any details
+        match pos {
+            PartOfSpeech::Noun { .. }
+            | PartOfSpeech::Adjective { .. }
+            | PartOfSpeech::Verb { .. } => Ok(pos),
+            _ => match tag.split_whitespace().nth(1) {
+                Some(other) => unknown(other),
+                None => Ok(pos),
+            },
+        }
+    }
+}
+
+impl fmt::Display for PartOfSpeech {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PartOfSpeech::Noun { declension, gender } => {
+                write!(f, "noun")?;
+                if let Some(d) = declension {
+                    write!(f, " {}", d)?;
+                }
+                if let Some(g) = gender {
+                    write!(f, " {}", g.abbreviation())?;
+                }
+                Ok(())
+            }
+            PartOfSpeech::Adjective { pattern } => match pattern {
+                Some(AdjectivePattern::FirstSecond) => write!(f, "adj 212"),
+                Some(AdjectivePattern::Third {
+                    terminations: Some(t),
+                }) => write!(f, "adj 3 {}", t),
+                Some(AdjectivePattern::Third { terminations: None }) => write!(f, "adj 3"),
+                None => write!(f, "adj"),
+            },
+            PartOfSpeech::Verb { conjugation, voice } => {
+                write!(f, "verb")?;
+                if let Some(c) = conjugation {
+                    write!(f, " {}", c)?;
+                }
+                match voice {
+                    Some(Voice::Deponent) => write!(f, " dep"),
+                    Some(Voice::SemiDeponent) => write!(f, " semi-dep"),
+                    _ => Ok(()),
+                }
+            }
+            PartOfSpeech::Adverb => write!(f, "adv"),
+            PartOfSpeech::Conjunction => write!(f, "conj"),
+            PartOfSpeech::Preposition => write!(f, "prep"),
+            PartOfSpeech::Pronoun => write!(f, "pronoun"),
+            PartOfSpeech::Interjection => write!(f, "interj"),
+            PartOfSpeech::Numeral => write!(f, "num"),
+        }
+    }
+}
+
+impl Gender {
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            Gender::Masculine => "m",
+            Gender::Feminine => "f",
+            Gender::Neuter => "n",
+            Gender::Common => "m/f",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gender::Masculine => "masculine",
+            Gender::Feminine => "feminine",
+            Gender::Neuter => "neuter",
+            Gender::Common => "masculine or feminine",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(tag: &str) -> PartOfSpeech {
+        tag.parse().unwrap()
+    }
+
+    #[test]
+    fn tags_with_details() {
+        assert_eq!(
+            parse("noun 3 f"),
+            PartOfSpeech::Noun {
+                declension: Some(3),
+                gender: Some(Gender::Feminine)
+            }
+        );
+        assert_eq!(
+            parse(" N. m/f "),
+            PartOfSpeech::Noun {
+                declension: None,
+                gender: Some(Gender::Common)
+            }
+        );
+        assert_eq!(
+            parse("adj 212"),
+            PartOfSpeech::Adjective {
+                pattern: Some(AdjectivePattern::FirstSecond)
+            }
+        );
+        assert_eq!(
+            parse("adj 3 2"),
+            PartOfSpeech::Adjective {
+                pattern: Some(AdjectivePattern::Third {
+                    terminations: Some(2)
+                })
+            }
+        );
+        assert_eq!(
+            parse("verb 3-io semi-dep"),
+            PartOfSpeech::Verb {
+                conjugation: Some(3),
+                voice: Some(Voice::SemiDeponent)
+            }
+        );
+        assert_eq!(parse("prep"), PartOfSpeech::Preposition);
+    }
+
+    #[test]
+    fn bad_tags() {
+        for bad in &[
+            "",
+            "noun 6",
+            "noun x",
+            "adj 3 4",
+            "adj 1",
+            "verb passive",
+            "prep abl",
+            "thing",
+        ] {
+            assert!(bad.parse::<PartOfSpeech>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for tag in &[
+            "noun 2 n",
+            "noun",
+            "adj 212",
+            "adj 3 1",
+            "adj 3",
+            "adj",
+            "verb 1",
+            "verb 2 dep",
+            "verb semi-dep",
+            "adv",
+            "conj",
+            "prep",
+            "pronoun",
+            "interj",
+            "num",
+        ] {
+            let pos = parse(tag);
+            assert_eq!(pos.to_string(), *tag);
+            assert_eq!(parse(&pos.to_string()), pos);
+        }
+    }
+
+    #[test]
+    fn markers_in_order() {
+        assert_eq!(
+            case_markers("instead of ABL; (+ACC) towards, ABL"),
+            [Case::Ablative, Case::Accusative]
+        );
+        assert!(case_markers("Able to ACCept").is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::config;
+use crate::spelling;
+use crate::{AnswerOption, GapType, Question};
+
+// English -> Latin questions, made by turning Latin -> English ones round:
+// the English answers become the prompt and the Latin prompt the answer.
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Direction {
+    #[default]
+    LatinToEnglish,
+    EnglishToLatin,
+    Both,
+}
+
+// How the Latin answers of a reversed question are marked
+#[derive(Debug, Clone)]
+pub struct Marking {
+    // for the word typed with its macrons
+    pub macron_mark: u8,
+    // for the word typed without its macrons, 0 to not accept it
+    pub plain_mark: u8,
+    // accept j for consonantal i, and i for j
+    pub ij: bool,
+    // accept v for consonantal u, and u for v
+    pub uv: bool,
+    // for the right stem with the wrong ending, 0 to leave it out
+    pub stem_mark: u8,
+}
+
+impl Default for Marking {
+    fn default() -> Self {
+        Marking {
+            macron_mark: 100,
+            plain_mark: 100,
+            ij: true,
+            uv: true,
+            stem_mark: 50,
+        }
+    }
+}
+
+// Apply a direction to Latin -> English questions. With both directions
+// the Latin -> English questions come first. Questions that were turned
+// round before, e.g. on import, are kept after them unless the same
+// question has just been made again.
+pub fn apply(questions: Vec<Question>, direction: Direction, marking: &Marking) -> Vec<Question> {
+    let (already, forward): (Vec<Question>, Vec<Question>) =
+        questions.into_iter().partition(|q| q.reversed);
+    let mut applied = match direction {
+        Direction::LatinToEnglish => forward,
+        Direction::EnglishToLatin => reverse_questions(&forward, marking),
+        Direction::Both => {
+            let mut reversed = reverse_questions(&forward, marking);
+            let mut both = forward;
+            both.append(&mut reversed);
+            both
+        }
+    };
+    for question in already {
+        if !applied
+            .iter()
+            .any(|q| q.reversed && q.latin == question.latin && q.test == question.test)
+        {
+            applied.push(question);
+        }
+    }
+    applied
+}
+
+// Only questions with typed answers can be reversed: there is no sense in
+// "masculine -> desperatio". Questions whose English is the same, like
+// bonus, bona, bonum, become one question that accepts any of them.
+pub fn reverse_questions(questions: &[Question], marking: &Marking) -> Vec<Question> {
+    let mut reversed: Vec<Question> = Vec::new();
+    for question in questions {
+        if question.reversed
+            || question.gap_type != GapType::ShortAnswer
+            || question.has_typed_extra_gaps()
+        {
+            continue;
+        }
+        let english = question
+            .answers
+            .iter()
+            .filter(|a| a.mark == 100)
+            .map(|a| a.answer.as_str())
+            .collect::<Vec<&str>>();
+        if english.is_empty() {
+            continue;
+        }
+        let prompt = english.join(", ");
+        let answers = latin_answers(&question.latin, marking);
+        match reversed
+            .iter_mut()
+            .find(|r| r.latin == prompt && r.test == question.test)
+        {
+            Some(existing) => merge_answers(&mut existing.answers, answers),
+            None => reversed.push(Question {
+                test: question.test,
+                reversed: true,
+                ..Question::new(prompt, answers)
+            }),
+        }
+    }
+    reversed
+}
+
+// Keep full marks in front of part marks, since Moodle uses the first match
+fn merge_answers(existing: &mut Vec<AnswerOption>, answers: Vec<AnswerOption>) {
+    for answer in answers {
+        if existing.iter().any(|a| a.answer == answer.answer) {
+            continue;
+        }
+        let position = existing
+            .iter()
+            .position(|a| a.mark < answer.mark)
+            .unwrap_or(existing.len());
+        existing.insert(position, answer);
+    }
+}
+
+// The Latin as written, then the other spellings allowed, then a wildcard
+// on the stem for part marks. For a headword like "desperatio, desperationis"
+// the first form on its own is accepted as well.
+pub fn latin_answers(latin: &str, marking: &Marking) -> Vec<AnswerOption> {
+    let latin = latin.trim();
+    let first = latin.split(',').next().unwrap_or(latin).trim();
+    let mut forms = vec![latin];
+    if first != latin {
+        forms.push(first);
+    }
+    let mut answers: Vec<AnswerOption> = Vec::new();
+    let mut add = |answer: String, mark: u8, feedback: &str| {
+        if mark > 0 && !answers.iter().any(|a: &AnswerOption| a.answer == answer) {
+            answers.push(AnswerOption {
+                mark,
+                answer,
+                feedback: feedback.to_string(),
+            });
+        }
+    };
+    // without macrons in the word there is only one spelling to mark
+    let mark = match spelling::has_macrons(latin) {
+        true => marking.macron_mark,
+        false => 100,
+    };
+    let correct = config::correct_feedback();
+    for form in &forms {
+        add(form.to_string(), mark, &correct);
+        for variant in spelling::spelling_variants(form, marking.ij, marking.uv) {
+            add(variant, mark, &correct);
+        }
+    }
+    if spelling::has_macrons(latin) {
+        let feedback = match marking.plain_mark {
+            100 => correct.as_str(),
+            _ => "Nearly! Don't forget the macrons.",
+        };
+        for form in &forms {
+            let plain = spelling::strip_macrons(form);
+            add(plain.clone(), marking.plain_mark, feedback);
+            for variant in spelling::spelling_variants(&plain, marking.ij, marking.uv) {
+                add(variant, marking.plain_mark, feedback);
+            }
+        }
+    }
+    if let Some(stem) = spelling::stem(&spelling::strip_macrons(first)) {
+        add(
+            format!("{}*", stem),
+            marking.stem_mark,
+            "Close! Check the ending.",
+        );
+    }
+    answers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::question;
+
+    fn prompts(questions: &[Question]) -> Vec<(&str, bool)> {
+        questions
+            .iter()
+            .map(|q| (q.latin.as_str(), q.reversed))
+            .collect()
+    }
+
+    #[test]
+    fn same_english_is_one_question() {
+        let questions = vec![question("et", "and"), question("atque", "and")];
+        let reversed = apply(questions, Direction::EnglishToLatin, &Marking::default());
+        assert_eq!(prompts(&reversed), [("and", true)]);
+        assert!(reversed[0].answers.iter().any(|a| a.answer == "atque"));
+    }
+
+    #[test]
+    fn reversed_questions_are_not_turned_round_again() {
+        let questions = vec![question("porto", "I carry"), question("bonus", "good")];
+        let once = apply(questions, Direction::Both, &Marking::default());
+        let expected = [
+            ("porto", false),
+            ("bonus", false),
+            ("I carry", true),
+            ("good", true),
+        ];
+        assert_eq!(prompts(&once), expected);
+        let twice = apply(once.clone(), Direction::Both, &Marking::default());
+        assert_eq!(prompts(&twice), expected);
+        let kept = apply(once.clone(), Direction::LatinToEnglish, &Marking::default());
+        assert_eq!(prompts(&kept), expected);
+        let english_only = apply(once, Direction::EnglishToLatin, &Marking::default());
+        assert_eq!(prompts(&english_only), [("I carry", true), ("good", true)]);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::Question;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_SESSION: &str = "./session.json";
+const AUTOSAVE_EXT: &str = "autosave";
+
+// Everything needed to pick up work where it was left off.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub questions: Vec<Question>,
+}
+
+pub fn save(path: &Path, questions: &[Question]) -> Result<(), Box<dyn Error>> {
+    let session = Session {
+        version: 1,
+        questions: questions.to_vec(),
+    };
+    // write to a temporary file first so a crash can't leave a half-written session
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let f = File::create(&tmp)?;
+        let mut writer = BufWriter::new(f);
+        serde_json::to_writer_pretty(&mut writer, &session)?;
+        writer.flush()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Vec<Question>, Box<dyn Error>> {
+    let f = File::open(path)?;
+    let session: Session = serde_json::from_reader(BufReader::new(f))?;
+    Ok(session.questions)
+}
+
+// The sidecar lives next to the session file, e.g. session.json.autosave
+pub fn autosave_path(session_file: Option<&Path>) -> PathBuf {
+    let session_file = session_file.unwrap_or_else(|| Path::new(DEFAULT_SESSION));
+    let mut name = session_file.as_os_str().to_owned();
+    name.push(".");
+    name.push(AUTOSAVE_EXT);
+    PathBuf::from(name)
+}
+
+pub fn autosave(session_file: Option<&Path>, questions: &[Question]) -> Result<(), Box<dyn Error>> {
+    save(&autosave_path(session_file), questions)
+}
+
+// True if there is an autosave that is more recent than the session file itself
+pub fn has_newer_autosave(session_file: &Path) -> bool {
+    let sidecar = autosave_path(Some(session_file));
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(&sidecar), modified(session_file)) {
+        (Some(auto), Some(saved)) => auto > saved,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+pub fn discard_autosave(session_file: Option<&Path>) {
+    let _ = fs::remove_file(autosave_path(session_file));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{question, temp_path};
+    use crate::{AnswerOption, Gap, GapType};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn saved_sessions_load_the_same() {
+        let mut principal_parts = question("porto,", "portare");
+        principal_parts.test = Some(20);
+        principal_parts.extra_gaps.push(Gap {
+            prompt: ",".to_string(),
+            gap_type: GapType::ShortAnswer,
+            answers: vec![AnswerOption {
+                mark: 100,
+                answer: "portavi".to_string(),
+                feedback: "Well done!".to_string(),
+            }],
+        });
+        let mut gender = question("civis: which gender?", "masculine");
+        gender.gap_type = GapType::MultiChoice;
+        let mut reversed = question("I carry", "porto");
+        reversed.reversed = true;
+        let questions = [principal_parts, gender, reversed];
+        let path = temp_path("session_round_trip.json");
+        save(&path, &questions).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", questions));
+    }
+
+    #[test]
+    fn only_a_later_autosave_is_newer() {
+        let path = temp_path("session_newer.json");
+        let sidecar = autosave_path(Some(&path));
+        assert!(!has_newer_autosave(&path));
+        // an autosave with no session file is work that was never saved
+        autosave(Some(&path), &[]).unwrap();
+        assert!(has_newer_autosave(&path));
+        save(&path, &[]).unwrap();
+        let now = SystemTime::now();
+        let touch = |p: &Path, time: SystemTime| {
+            File::options()
+                .write(true)
+                .open(p)
+                .unwrap()
+                .set_modified(time)
+                .unwrap()
+        };
+        touch(&path, now);
+        touch(&sidecar, now - Duration::from_secs(60));
+        assert!(!has_newer_autosave(&path));
+        touch(&sidecar, now + Duration::from_secs(60));
+        assert!(has_newer_autosave(&path));
+        discard_autosave(Some(&path));
+        assert!(!has_newer_autosave(&path));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+// Latin spellings a student might type for the same word: with or without
+// macrons, i or j, u or v.
+
+use unicode_normalization::UnicodeNormalization;
+
+const MACRONS: [(char, char); 12] = [
+    ('ā', 'a'),
+    ('ē', 'e'),
+    ('ī', 'i'),
+    ('ō', 'o'),
+    ('ū', 'u'),
+    ('ȳ', 'y'),
+    ('Ā', 'A'),
+    ('Ē', 'E'),
+    ('Ī', 'I'),
+    ('Ō', 'O'),
+    ('Ū', 'U'),
+    ('Ȳ', 'Y'),
+];
+
+// Vocab lists typed on different machines may write ā as a followed by a
+// combining macron. Composing them means every ā is the same character.
+pub fn normalize(s: &str) -> String {
+    s.nfc().collect()
+}
+
+pub fn strip_macrons(s: &str) -> String {
+    s.chars()
+        .map(|c| match MACRONS.iter().find(|(long, _)| *long == c) {
+            Some((_, short)) => *short,
+            None => c,
+        })
+        .collect()
+}
+
+pub fn has_macrons(s: &str) -> bool {
+    s.chars()
+        .any(|c| MACRONS.iter().any(|(long, _)| *long == c))
+}
+
+fn is_vowel(c: char) -> bool {
+    "aeiouyāēīōūȳAEIOUYĀĒĪŌŪȲ".contains(c)
+}
+
+// The other ways of writing a word with i/j and u/v, not including the word
+// itself, e.g. iuuenis -> juvenis, iuvenis, juuenis. With `ij` false only
+// i is used for consonantal i, and with `uv` false only u for consonantal u.
+pub fn spelling_variants(s: &str, ij: bool, uv: bool) -> Vec<String> {
+    let base = without_j_v(s);
+    let with_j = consonantal(&base, &['i', 'I'], |c| if c == 'i' { 'j' } else { 'J' });
+    // marking v after j means iuuenis is read ju-ve-nis, not i-vu-e-nis
+    let with_j_v = consonantal(&with_j, &['u', 'U'], |c| if c == 'u' { 'v' } else { 'V' });
+    let with_v = with_j_v.replace('j', "i").replace('J', "I");
+    let mut variants: Vec<String> = vec![base];
+    if ij {
+        variants.push(with_j);
+    }
+    if uv {
+        variants.push(with_v);
+    }
+    if ij && uv {
+        variants.push(with_j_v);
+    }
+    let mut unique: Vec<String> = Vec::new();
+    for variant in variants {
+        if variant != s && !unique.contains(&variant) {
+            unique.push(variant);
+        }
+    }
+    unique
+}
+
+// Every j as i and every v as u
+fn without_j_v(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'j' => 'i',
+            'J' => 'I',
+            'v' => 'u',
+            'V' => 'U',
+            _ => c,
+        })
+        .collect()
+}
+
+// Replace i or u where it is a consonant: at the start of a word before a
+// vowel, or between two vowels (maior, nouus)
+fn consonantal(s: &str, letters: &[char], replace: fn(char) -> char) -> String {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut result = String::with_capacity(s.len());
+    for (num, c) in chars.iter().enumerate() {
+        let before = match num {
+            0 => None,
+            _ => Some(chars[num - 1]),
+        };
+        let after = chars.get(num + 1).copied();
+        let starts_word = before.map(|b| !b.is_alphabetic()).unwrap_or(true);
+        let vowel_after = after.map(is_vowel).unwrap_or(false);
+        let vowel_before = before.map(is_vowel).unwrap_or(false);
+        // qu and gu (quis, lingua) keep their u
+        let after_q = before.map(|b| "qQgG".contains(b)).unwrap_or(false);
+        if letters.contains(c) && vowel_after && (starts_word || vowel_before) && !after_q {
+            result.push(replace(*c));
+        } else {
+            result.push(*c);
+        }
+    }
+    result
+}
+
+// The word with its ending taken off, for a wildcard answer that gives
+// part marks for the right word in the wrong form, e.g. port for porto.
+// Phrases and very short words have no useful stem.
+pub fn stem(s: &str) -> Option<String> {
+    let word = s.trim();
+    if word.contains(' ') {
+        return None;
+    }
+    let chars = word.chars().collect::<Vec<char>>();
+    let cut = match chars.len() {
+        0..=3 => return None,
+        4 => 1,
+        _ => 2,
+    };
+    Some(chars[..chars.len() - cut].iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macrons() {
+        assert_eq!(strip_macrons("portāre, Ōceanus"), "portare, Oceanus");
+        assert!(has_macrons("rēx"));
+        assert!(!has_macrons("rex"));
+        // a followed by a combining macron
+        let decomposed = "porta\u{304}re";
+        assert!(!has_macrons(decomposed));
+        assert!(has_macrons(&normalize(decomposed)));
+        assert_eq!(normalize(decomposed), "portāre");
+    }
+
+    #[test]
+    fn i_j_and_u_v() {
+        let mut variants = spelling_variants("iuuenis", true, true);
+        variants.sort();
+        assert_eq!(variants, ["iuvenis", "juuenis", "juvenis"]);
+        assert_eq!(spelling_variants("iuuenis", false, true), ["iuvenis"]);
+        assert_eq!(spelling_variants("iuuenis", true, false), ["juuenis"]);
+        assert!(spelling_variants("iuuenis", false, false).is_empty());
+        // the word as typed is never a variant of itself
+        assert!(!spelling_variants("Iulius", true, true).contains(&"Iulius".to_string()));
+        assert!(spelling_variants("Iulius", true, true).contains(&"Julius".to_string()));
+    }
+
+    #[test]
+    fn vowels_stay_vowels() {
+        // qu and gu keep their u, and i between consonants is a vowel
+        assert!(spelling_variants("quis", true, true).is_empty());
+        assert!(spelling_variants("lingua", true, true).is_empty());
+        assert!(spelling_variants("fīlia", true, true).is_empty());
+        assert_eq!(spelling_variants("maior", true, false), ["major"]);
+        assert_eq!(spelling_variants("nouus", false, true), ["novus"]);
+        assert_eq!(spelling_variants("novus", false, false), ["nouus"]);
+    }
+
+    #[test]
+    fn stems() {
+        assert_eq!(stem("porto").as_deref(), Some("por"));
+        assert_eq!(stem("domus").as_deref(), Some("dom"));
+        assert_eq!(stem("iter").as_deref(), Some("ite"));
+        assert_eq!(stem("et"), None);
+        assert_eq!(stem("in via"), None);
+    }
+}
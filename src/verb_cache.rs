@@ -0,0 +1,268 @@
+use csv::Reader;
+use lazy_static::lazy_static;
+use mw_past::Verb;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::panic;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Results of mw_past::lookup, kept so re-imports are instant and work offline
+pub const CACHE_FILE: &str = "./verb-cache.json";
+// Hand-written English forms for verbs the lookup gets wrong.
+// Columns: English,Present,Past Simple,Past Participle[,Asterisked]
+pub const OVERRIDES_FILE: &str = "./verb-overrides.csv";
+
+// The English forms build_verb needs, copied out of mw_past::Verb
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerbForms {
+    pub present: String,
+    pub past_simple: String,
+    pub past_part: String,
+    pub asterisked: String,
+}
+
+impl VerbForms {
+    fn from_verb(verb: &Verb) -> VerbForms {
+        VerbForms {
+            present: verb.present.clone(),
+            past_simple: verb.past_simple.clone(),
+            past_part: verb.past_part.clone(),
+            asterisked: verb.asterisked.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.present.trim().is_empty()
+            || self.past_simple.trim().is_empty()
+            || self.past_part.trim().is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Override {
+    #[serde(rename = "English")]
+    english: String,
+    #[serde(rename = "Present")]
+    present: String,
+    #[serde(rename = "Past Simple")]
+    past_simple: String,
+    #[serde(rename = "Past Participle")]
+    past_part: String,
+    #[serde(rename = "Asterisked", default)]
+    asterisked: String,
+}
+
+// A lookup that needs a line in the overrides file
+#[derive(Debug, Clone)]
+pub struct LookupProblem {
+    pub english: String,
+    pub reason: String,
+}
+
+#[derive(Default)]
+struct VerbCache {
+    verbs: BTreeMap<String, VerbForms>,
+    overrides: BTreeMap<String, VerbForms>,
+    problems: Vec<LookupProblem>,
+    changed: bool,
+}
+
+lazy_static! {
+    static ref CACHE: Mutex<VerbCache> = Mutex::new(VerbCache::open());
+}
+
+impl VerbCache {
+    fn open() -> VerbCache {
+        let mut cache = VerbCache::default();
+        // a missing or damaged cache just means looking everything up again
+        if let Ok(f) = File::open(CACHE_FILE) {
+            if let Ok(verbs) = serde_json::from_reader(BufReader::new(f)) {
+                cache.verbs = verbs;
+            }
+        }
+        if let Err(e) = cache.load_overrides() {
+            cache.problems.push(LookupProblem {
+                english: OVERRIDES_FILE.to_string(),
+                reason: format!("unable to read overrides: {}", e),
+            });
+        }
+        cache
+    }
+
+    fn load_overrides(&mut self) -> Result<(), Box<dyn Error>> {
+        self.overrides.clear();
+        if !Path::new(OVERRIDES_FILE).exists() {
+            return Ok(());
+        }
+        let mut rdr = Reader::from_path(OVERRIDES_FILE)?;
+        for result in rdr.deserialize() {
+            let row: Override = result?;
+            let asterisked = match row.asterisked.trim() {
+                "" => format!("*{}*", row.present.trim()),
+                a => a.to_string(),
+            };
+            self.overrides.insert(
+                key(&row.english),
+                VerbForms {
+                    present: row.present.trim().to_string(),
+                    past_simple: row.past_simple.trim().to_string(),
+                    past_part: row.past_part.trim().to_string(),
+                    asterisked,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    // Overrides first, then the cache
+    fn get(&self, key: &str) -> Option<VerbForms> {
+        self.overrides
+            .get(key)
+            .or_else(|| self.verbs.get(key))
+            .cloned()
+    }
+
+    // Keep what a lookup found, or note that it found nothing. `None` is a
+    // lookup that failed, which is answered with the English as it is.
+    fn record(&mut self, key: &str, found: Option<VerbForms>) -> VerbForms {
+        match found {
+            Some(forms) => {
+                if forms.is_empty() {
+                    self.add_problem(key, "lookup returned no forms");
+                } else {
+                    self.verbs.insert(key.to_string(), forms.clone());
+                    self.changed = true;
+                }
+                forms
+            }
+            None => {
+                self.add_problem(key, "lookup failed");
+                VerbForms {
+                    present: key.to_string(),
+                    past_simple: key.to_string(),
+                    past_part: key.to_string(),
+                    asterisked: format!("*{}*", key),
+                }
+            }
+        }
+    }
+
+    // A verb is only listed once, however many times it was looked up
+    fn add_problem(&mut self, english: &str, reason: &str) {
+        if !self.problems.iter().any(|p| p.english == english) {
+            self.problems.push(LookupProblem {
+                english: english.to_string(),
+                reason: reason.to_string(),
+            });
+        }
+    }
+}
+
+fn key(english: &str) -> String {
+    english.trim().to_lowercase()
+}
+
+// Overrides first, then the cache, and only then mw_past::lookup
+pub fn lookup(english: &str) -> VerbForms {
+    let key = key(english);
+    if let Some(forms) = CACHE.lock().unwrap().get(&key) {
+        return forms;
+    }
+    // don't hold the lock while waiting on the lookup
+    let word = key.clone();
+    let found = panic::catch_unwind(move || mw_past::lookup(&word))
+        .ok()
+        .map(|verb| VerbForms::from_verb(&verb));
+    CACHE.lock().unwrap().record(&key, found)
+}
+
+// Write out any new lookups. Called once an import has finished.
+pub fn save() -> Result<(), Box<dyn Error>> {
+    let mut cache = CACHE.lock().unwrap();
+    if !cache.changed {
+        return Ok(());
+    }
+    let tmp = format!("{}.tmp", CACHE_FILE);
+    {
+        let f = File::create(&tmp)?;
+        let mut writer = BufWriter::new(f);
+        serde_json::to_writer_pretty(&mut writer, &cache.verbs)?;
+        writer.flush()?;
+    }
+    fs::rename(&tmp, CACHE_FILE)?;
+    cache.changed = false;
+    Ok(())
+}
+
+// Pick up edits to the overrides file without restarting
+pub fn reload_overrides() -> Result<(), Box<dyn Error>> {
+    CACHE.lock().unwrap().load_overrides()
+}
+
+pub fn problems() -> Vec<LookupProblem> {
+    CACHE.lock().unwrap().problems.clone()
+}
+
+pub fn clear_problems() {
+    CACHE.lock().unwrap().problems.clear();
+}
+
+// Lets tests build verbs without looking anything up
+#[cfg(test)]
+pub(crate) fn add_override(english: &str, present: &str, past_simple: &str, past_part: &str) {
+    CACHE.lock().unwrap().overrides.insert(
+        key(english),
+        VerbForms {
+            present: present.to_string(),
+            past_simple: past_simple.to_string(),
+            past_part: past_part.to_string(),
+            asterisked: format!("*{}*", present),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forms(present: &str, past: &str) -> VerbForms {
+        VerbForms {
+            present: present.to_string(),
+            past_simple: past.to_string(),
+            past_part: past.to_string(),
+            asterisked: format!("*{}*", present),
+        }
+    }
+
+    #[test]
+    fn overrides_beat_the_cache() {
+        let mut cache = VerbCache::default();
+        cache.verbs.insert("lie".to_string(), forms("lie", "lied"));
+        assert_eq!(cache.get("lie").unwrap().past_simple, "lied");
+        cache
+            .overrides
+            .insert("lie".to_string(), forms("lie", "lay"));
+        assert_eq!(cache.get("lie").unwrap().past_simple, "lay");
+        assert!(cache.get("sit").is_none());
+    }
+
+    #[test]
+    fn empty_lookups_are_listed_once_and_not_kept() {
+        let mut cache = VerbCache::default();
+        for _ in 0..2 {
+            cache.record("ought", Some(forms("ought", "")));
+        }
+        assert_eq!(cache.problems.len(), 1);
+        assert_eq!(cache.problems[0].english, "ought");
+        assert_eq!(cache.problems[0].reason, "lookup returned no forms");
+        assert!(cache.get("ought").is_none());
+        assert!(!cache.changed);
+        cache.record("carry", Some(forms("carry", "carried")));
+        assert!(cache.get("carry").is_some());
+        assert!(cache.changed);
+    }
+}